@@ -1,4 +1,5 @@
 // https://github.com/microsoft/vscode/blob/d187d50a482ff80dcf74c35affb09dda1a7cd2fe/src/vs/platform/extensionManagement/common/extensionGalleryService.ts
+mod backend;
 mod enums;
 mod extensions;
 mod flags;
@@ -11,6 +12,7 @@ mod version;
 pub use extensions::*;
 pub use gallery_extension::*;
 
+pub use backend::*;
 pub use enums::*;
 pub use flags::*;
 pub use http_client::*;
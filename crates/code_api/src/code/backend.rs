@@ -0,0 +1,261 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use reqwest::{Certificate, Method, NoProxy, Proxy, RequestBuilder};
+use tracing::*;
+
+use crate::config::{Auth, NetworkConfig, ResolvedCredential};
+
+/// Sends the json `body` of a [`Query`](super::Query) to the gallery and
+/// returns the raw response.
+pub trait GalleryBackend: Debug + Send + Sync {
+    fn post(&self, body: String) -> BoxFuture<'_, anyhow::Result<String>>;
+}
+
+/// The live marketplace, or the private gallery of `gallery_url`.
+#[derive(Debug, Clone)]
+pub struct ReqwestBackend {
+    pub client: reqwest::Client,
+    url: String,
+    credentials: Vec<ResolvedCredential>,
+}
+
+/// The proxies of `HTTPS_PROXY`, `HTTP_PROXY` and `ALL_PROXY`, upper or lower case.
+fn env_proxies() -> anyhow::Result<Vec<Proxy>> {
+    let var = |key: &str| {
+        std::env::var(key.to_lowercase())
+            .or_else(|_| std::env::var(key))
+            .ok()
+            .filter(|value| !value.is_empty())
+    };
+
+    let mut res = vec![];
+    if let Some(proxy) = var("HTTPS_PROXY") {
+        res.push(Proxy::https(proxy)?);
+    }
+    if let Some(proxy) = var("HTTP_PROXY") {
+        res.push(Proxy::http(proxy)?);
+    }
+    if let Some(proxy) = var("ALL_PROXY") {
+        res.push(Proxy::all(proxy)?);
+    }
+    Ok(res)
+}
+
+impl ReqwestBackend {
+    pub fn new(network: &NetworkConfig) -> anyhow::Result<Self> {
+        let mut builder = reqwest::Client::builder().gzip(true);
+        let no_proxy = network.no_proxy.as_deref().and_then(NoProxy::from_string);
+        match &network.proxy {
+            Some(proxy) => builder = builder.proxy(Proxy::all(proxy)?.no_proxy(no_proxy)),
+            // reqwest would take the proxies of the environment with its `NO_PROXY`
+            None if no_proxy.is_some() => {
+                for proxy in env_proxies()? {
+                    builder = builder.proxy(proxy.no_proxy(no_proxy.clone()));
+                }
+            }
+            None => {}
+        }
+        for path in &network.ca_certificates {
+            let pem = std::fs::read(path)
+                .map_err(|err| anyhow!("read {} failed: {err}", path.display()))?;
+            for cert in Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if let Some(user_agent) = &network.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(secs) = network.connect_timeout {
+            builder = builder.connect_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = network.read_timeout {
+            builder = builder.read_timeout(Duration::from_secs(secs));
+        }
+
+        Ok(Self {
+            client: builder.build()?,
+            url: network.gallery().into(),
+            credentials: network.resolve_credentials(),
+        })
+    }
+
+    pub fn has_credential(&self, url: &str) -> bool {
+        self.credential(url).is_some()
+    }
+
+    fn credential(&self, url: &str) -> Option<&Auth> {
+        self.credentials
+            .iter()
+            .find(|item| item.matches(url))
+            .map(|item| &item.auth)
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match self.credential(url) {
+            Some(Auth::Basic { username, password }) => {
+                request.basic_auth(username, Some(password))
+            }
+            Some(Auth::Bearer(token)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Fetches a file with the credentials of its host.
+    pub async fn download(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        trace!("download {url}");
+        Ok(self
+            .request(Method::GET, url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec())
+    }
+}
+
+impl GalleryBackend for ReqwestBackend {
+    fn post(&self, body: String) -> BoxFuture<'_, anyhow::Result<String>> {
+        Box::pin(async move {
+            trace!("send request: {body}");
+            Ok(self
+                .request(Method::POST, &self.url)
+                .header(
+                    "Accept",
+                    "Application/json; charset=utf-8; api-version=7.2-preview.1",
+                )
+                .header("Content-Type", "application/json")
+                .body(body)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?)
+        })
+    }
+}
+
+/// A json file of values keyed by request, sorted so that recordings diff well.
+#[derive(Debug)]
+pub struct Fixture {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, String>>,
+}
+
+impl Fixture {
+    /// A missing file is an empty fixture.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Default::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    /// Stores `value` and rewrites the file.
+    pub fn insert(&self, key: &str, value: &str) -> anyhow::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.into(), value.into());
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&*entries)?)?;
+        Ok(())
+    }
+}
+
+/// Replays the responses of a [`Fixture`], or records them from another backend.
+#[derive(Debug)]
+pub struct FixtureBackend {
+    fixture: Fixture,
+    inner: Option<Arc<dyn GalleryBackend>>,
+}
+
+impl FixtureBackend {
+    /// Answers from `path` only, a query without a recorded response is an error.
+    pub fn replay(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(Self {
+            fixture: Fixture::open(path)?,
+            inner: None,
+        })
+    }
+
+    /// Sends every query to `inner` and writes the responses to `path`.
+    pub fn record(path: impl AsRef<Path>, inner: Arc<dyn GalleryBackend>) -> anyhow::Result<Self> {
+        Ok(Self {
+            fixture: Fixture::open(path)?,
+            inner: Some(inner),
+        })
+    }
+}
+
+impl GalleryBackend for FixtureBackend {
+    fn post(&self, body: String) -> BoxFuture<'_, anyhow::Result<String>> {
+        Box::pin(async move {
+            match &self.inner {
+                Some(inner) => {
+                    let response = inner.post(body.clone()).await?;
+                    self.fixture.insert(&body, &response)?;
+                    Ok(response)
+                }
+                None => self
+                    .fixture
+                    .get(&body)
+                    .ok_or_else(|| anyhow!("no recorded response for {body}")),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Echo;
+
+    impl GalleryBackend for Echo {
+        fn post(&self, body: String) -> BoxFuture<'_, anyhow::Result<String>> {
+            Box::pin(async move { Ok(format!("response of {body}")) })
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!(
+            "code-api-fixture-{}/gallery.json",
+            std::process::id()
+        ));
+
+        let recorder = FixtureBackend::record(&path, Arc::new(Echo)).unwrap();
+        let response = futures::executor::block_on(recorder.post("query".into())).unwrap();
+        assert_eq!(response, "response of query");
+
+        let replayer = FixtureBackend::replay(&path).unwrap();
+        assert_eq!(
+            futures::executor::block_on(replayer.post("query".into())).unwrap(),
+            response
+        );
+        assert!(futures::executor::block_on(replayer.post("other".into())).is_err());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -32,6 +32,8 @@ pub enum PropertyType {
     WebExtension,
     SponsorLink,
     SupportLink,
+    LearnLink,
+    SourceLink,
     ExecutesCode,
 }
 
@@ -45,6 +47,8 @@ impl PropertyType {
     pub const WEB_EXTENSION: &str = "Microsoft.VisualStudio.Code.WebExtension";
     pub const SPONSOR_LINK: &str = "Microsoft.VisualStudio.Code.SponsorLink";
     pub const SUPPORT_LINK: &str = "Microsoft.VisualStudio.Services.Links.Support";
+    pub const LEARN_LINK: &str = "Microsoft.VisualStudio.Services.Links.Learn";
+    pub const SOURCE_LINK: &str = "Microsoft.VisualStudio.Services.Links.Source";
     pub const EXECUTES_CODE: &str = "Microsoft.VisualStudio.Code.ExecutesCode";
 }
 
@@ -67,12 +71,15 @@ impl From<&PropertyType> for &str {
             PropertyType::WebExtension => "Microsoft.VisualStudio.Code.WebExtension",
             PropertyType::SponsorLink => "Microsoft.VisualStudio.Code.SponsorLink",
             PropertyType::SupportLink => "Microsoft.VisualStudio.Services.Links.Support",
+            PropertyType::LearnLink => "Microsoft.VisualStudio.Services.Links.Learn",
+            PropertyType::SourceLink => "Microsoft.VisualStudio.Services.Links.Source",
             PropertyType::ExecutesCode => "Microsoft.VisualStudio.Code.ExecutesCode",
         }
     }
 }
 
 #[api]
+#[derive(PartialEq, Eq, Copy)]
 pub struct SortOrder(u8);
 
 impl SortOrder {
@@ -82,6 +89,7 @@ impl SortOrder {
 }
 
 #[api]
+#[derive(PartialEq, Eq, Copy)]
 pub struct SortBy(u8);
 
 impl SortBy {
@@ -38,7 +38,7 @@ pub struct IRawGalleryExtension {
     pub publisher: IRawGalleryExtensionPublisher,
     pub versions: Vec<IRawGalleryExtensionVersion>,
     pub statistics: Vec<IRawGalleryExtensionStatistics>,
-    pub tags: Option<String>,
+    pub tags: Option<Vec<String>>,
     pub release_date: String,
     pub published_date: String,
     pub last_updated: String,
@@ -46,6 +46,19 @@ pub struct IRawGalleryExtension {
     pub flags: String,
 }
 
+impl IRawGalleryExtension {
+    pub const STATISTIC_INSTALL: &str = "install";
+    pub const STATISTIC_AVERAGE_RATING: &str = "averagerating";
+    pub const STATISTIC_RATING_COUNT: &str = "ratingcount";
+
+    pub fn get_statistic(&self, statistic_name: &str) -> Option<f64> {
+        self.statistics
+            .iter()
+            .find(|item| item.statistic_name == statistic_name)
+            .map(|item| item.value)
+    }
+}
+
 impl Display for IRawGalleryExtension {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -63,6 +76,16 @@ pub struct IRawGalleryExtensionsResult {
 }
 
 impl IRawGalleryExtensionsResult {
+    pub fn get_total_count(&self) -> Option<u64> {
+        self.result_metadata
+            .iter()
+            .find(|item| item.metadata_type == "ResultCount")?
+            .metadata_items
+            .iter()
+            .find(|item| item.name == "TotalCount")
+            .map(|item| item.count)
+    }
+
     pub fn get_target_platform(&self) -> Vec<TargetPlatform> {
         match self
             .result_metadata
@@ -35,6 +35,26 @@ impl IRawGalleryExtensionVersion {
         }
     }
 
+    /// The proposed VS Code APIs this version enables, empty if none.
+    pub fn get_api_proposals(&self) -> Vec<&str> {
+        self.get_property(PropertyType::ENABLED_API_PROPOSALS)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn get_property(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|item| item.key == key)
+            .map(|item| item.value.as_str())
+    }
+
     pub fn is_pre_release_version(&self) -> bool {
         let values = self
             .properties
@@ -1,23 +1,89 @@
 use crate::{
-    code::{self, IRawGalleryExtensionsResult, IRawGalleryQueryResult, TargetPlatform},
-    config::Extension,
+    code::{
+        self, IRawGalleryExtension, IRawGalleryExtensionsResult, IRawGalleryQueryResult,
+        TargetPlatform,
+    },
+    config::{Extension, NetworkConfig},
 };
 
-use super::Query;
+use std::{fmt::Debug, sync::Arc};
+
+use super::{GalleryBackend, Query, ReqwestBackend};
 use anyhow::anyhow;
 use async_stream::try_stream;
 use futures::stream::Stream;
 use tracing::*;
 
+/// Storage of raw gallery responses, keyed by the request body.
+pub trait QueryCache: Debug + Send + Sync {
+    fn get(&self, body: &str) -> Option<String>;
+    fn insert(&self, body: &str, response: &str);
+}
+
 #[derive(Debug, Clone)]
 pub struct HttpClient {
-    pub client: reqwest::Client,
+    backend: Arc<dyn GalleryBackend>,
+    cache: Option<Arc<dyn QueryCache>>,
+    offline: bool,
 }
 
 impl HttpClient {
+    pub const GALLERY_URL: &str =
+        "https://marketplace.visualstudio.com/_apis/public/gallery/extensionquery";
+
     pub fn new() -> anyhow::Result<Self> {
-        let client = reqwest::Client::builder().gzip(true).build()?;
-        Ok(Self { client })
+        Self::with_network(&Default::default())
+    }
+
+    /// A client of the live marketplace whose requests follow `network`.
+    pub fn with_network(network: &NetworkConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            backend: Arc::new(ReqwestBackend::new(network)?),
+            cache: None,
+            offline: false,
+        })
+    }
+
+    pub fn with_backend(mut self, backend: Arc<dyn GalleryBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Arc<dyn QueryCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Answer queries from the cache only, a miss is an error instead of a request.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub async fn query(&self, query: &Query) -> anyhow::Result<IRawGalleryQueryResult> {
+        let body = serde_json::to_string(query)?;
+        if let Some(response) = self.cache.as_ref().and_then(|cache| cache.get(&body)) {
+            match serde_json::from_str(&response) {
+                Ok(v) => {
+                    trace!("use cached response of {body}");
+                    return Ok(v);
+                }
+                Err(err) => debug!("bad cached response: {err}"),
+            }
+        }
+
+        if self.offline {
+            return Err(anyhow!("no cached response for {body} in offline mode"));
+        }
+
+        let response = self.backend.post(body.clone()).await?;
+        let result = serde_json::from_str(&response)?;
+
+        if let Some(cache) = &self.cache {
+            cache.insert(&body, &response);
+        }
+
+        Ok(result)
     }
 
     pub fn get_extension_response(
@@ -29,21 +95,7 @@ impl HttpClient {
         try_stream! {
             loop {
                 let query = Query::new(&extensions, page_number);
-                let body = serde_json::to_string(&query)?;
-                trace!("send request: {body}");
-                let response = self
-                    .client
-                    .post("https://marketplace.visualstudio.com/_apis/public/gallery/extensionquery")
-                    .header(
-                        "Accept",
-                        "Application/json; charset=utf-8; api-version=7.2-preview.1",
-                    )
-                    .header("Content-Type", "application/json")
-                    .body(body.clone())
-                    .send()
-                    .await?
-                    .json::<IRawGalleryQueryResult>()
-                    .await?;
+                let response = self.query(&query).await?;
 
                 if response.results.is_empty() {
                     break;
@@ -64,23 +116,43 @@ impl HttpClient {
         extension_name: String,
     ) -> anyhow::Result<IRawGalleryExtensionsResult> {
         let query = Query::create_search(publisher_name, extension_name);
-        let body = serde_json::to_string(&query)?;
-        let txt = self
-            .client
-            .post("https://marketplace.visualstudio.com/_apis/public/gallery/extensionquery")
-            .header(
-                "Accept",
-                "Application/json; charset=utf-8; api-version=7.2-preview.1",
-            )
-            .header("Content-Type", "application/json")
-            .body(body)
-            .send()
-            .await?
-            .json::<code::IRawGalleryQueryResult>()
-            .await?;
+        let txt = self.query(&query).await?;
         txt.results.into_iter().next().ok_or(anyhow!("Unknown"))
     }
 
+    pub async fn get_extension_detail(
+        &self,
+        publisher_name: &str,
+        extension_name: &str,
+    ) -> anyhow::Result<Option<IRawGalleryExtension>> {
+        trace!("get detail of {publisher_name}.{extension_name}");
+        let query = Query::create_detail(publisher_name, extension_name);
+        Ok(self
+            .query(&query)
+            .await?
+            .results
+            .into_iter()
+            .flat_map(|item| item.extensions)
+            .find(|item| {
+                item.publisher
+                    .publisher_name
+                    .eq_ignore_ascii_case(publisher_name)
+                    && item.extension_name.eq_ignore_ascii_case(extension_name)
+            }))
+    }
+
+    pub async fn search_extensions(&self, text: &str) -> anyhow::Result<Vec<IRawGalleryExtension>> {
+        trace!("search extensions by {text}");
+        let query = Query::create_text_search(text);
+        Ok(self
+            .query(&query)
+            .await?
+            .results
+            .into_iter()
+            .flat_map(|item| item.extensions)
+            .collect())
+    }
+
     pub async fn get_extension_target_platform(
         &self,
         publisher_name: String,
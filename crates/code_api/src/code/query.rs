@@ -37,17 +37,37 @@ impl Query {
                 ..Default::default()
             }],
             asset_types: Default::default(),
-            flags: RequestFlags::default().bits(),
+            flags: (RequestFlags::default()
+                | RequestFlags::IncludeCategoryAndTags
+                | RequestFlags::IncludeStatistics)
+                .bits(),
         }
     }
 
+    /// Every version of a single extension together with its gallery metadata.
+    pub fn create_detail(publisher_name: &str, extension_name: &str) -> Self {
+        Self::new(
+            &[Extension {
+                publisher_name: publisher_name.into(),
+                extension_name: extension_name.into(),
+                asset_url: None,
+                system: None,
+            }],
+            1,
+        )
+    }
+
     pub fn create_search(publisher_name: String, extension_name: String) -> Self {
+        Self::create_text_search(&format!("{}.{}", publisher_name, extension_name))
+    }
+
+    pub fn create_text_search(text: &str) -> Self {
         Query {
             filters: vec![IQueryState {
                 criteria: vec![
                     ICriterium {
                         filter_type: FilterType::SEARCH_TEXT,
-                        value: format!("{}.{}", publisher_name, extension_name),
+                        value: text.into(),
                     },
                     ICriterium {
                         filter_type: FilterType::TARGET,
@@ -64,4 +84,61 @@ impl Query {
             flags: RequestFlags::IncludeLatestVersionOnly.bits(),
         }
     }
+
+    /// A marketplace search as done by the extensions view of VS Code.
+    pub fn create_gallery_search(
+        text: &str,
+        categories: &[String],
+        tags: &[String],
+        sort_by: SortBy,
+        page_number: u64,
+        page_size: u64,
+    ) -> Self {
+        let sort_order = if sort_by == SortBy::TITLE {
+            SortOrder::ASCENDING
+        } else {
+            SortOrder::DESCENDING
+        };
+
+        let criteria = std::iter::once(ICriterium {
+            filter_type: FilterType::SEARCH_TEXT,
+            value: text.into(),
+        })
+        .chain(categories.iter().map(|item| ICriterium {
+            filter_type: FilterType::CATEGORY,
+            value: item.clone(),
+        }))
+        .chain(tags.iter().map(|item| ICriterium {
+            filter_type: FilterType::TAG,
+            value: item.clone(),
+        }))
+        .chain([
+            ICriterium {
+                filter_type: FilterType::TARGET,
+                value: "Microsoft.VisualStudio.Code".into(),
+            },
+            ICriterium {
+                filter_type: FilterType::EXCLUDE_WITH_FLAGS,
+                value: "4096".into(),
+            },
+        ])
+        .collect();
+
+        Query {
+            filters: vec![IQueryState {
+                page_number,
+                page_size,
+                sort_by,
+                sort_order,
+                criteria,
+                ..Default::default()
+            }],
+            asset_types: Default::default(),
+            flags: (RequestFlags::IncludeLatestVersionOnly
+                | RequestFlags::IncludeVersions
+                | RequestFlags::IncludeStatistics
+                | RequestFlags::IncludeCategoryAndTags)
+                .bits(),
+        }
+    }
 }
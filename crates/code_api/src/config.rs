@@ -1,4 +1,8 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
+use tracing::*;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct Extension {
@@ -8,6 +12,13 @@ pub struct Extension {
     pub system: Option<SystemContext>,
 }
 
+impl Extension {
+    /// `publisher.name` in lowercase, ids of the marketplace ignore case.
+    pub fn id(&self) -> String {
+        format!("{}.{}", self.publisher_name, self.extension_name).to_lowercase()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct SystemContext {
     arch: String,
@@ -22,3 +33,255 @@ impl Default for SystemContext {
         }
     }
 }
+
+/// How requests reach the network, shared by gallery queries and downloads.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Proxy for every request, `HTTPS_PROXY` and friends are used without it.
+    pub proxy: Option<String>,
+    /// Comma separated hosts which bypass `proxy`, or the proxies of the
+    /// environment without it.
+    pub no_proxy: Option<String>,
+    /// PEM files trusted in addition to the built-in roots.
+    pub ca_certificates: Vec<PathBuf>,
+    pub user_agent: Option<String>,
+    /// Seconds.
+    pub connect_timeout: Option<u64>,
+    /// Seconds without receiving any data.
+    pub read_timeout: Option<u64>,
+    /// Endpoint of extension queries, for private galleries.
+    pub gallery_url: Option<String>,
+    pub credentials: Vec<Credential>,
+    /// Basic auth for hosts without an entry in `credentials`.
+    pub netrc_file: Option<PathBuf>,
+}
+
+impl NetworkConfig {
+    /// `gallery_url`, or the public marketplace.
+    pub fn gallery(&self) -> &str {
+        self.gallery_url
+            .as_deref()
+            .unwrap_or(crate::code::HttpClient::GALLERY_URL)
+    }
+
+    /// Credentials by host, those of `credentials` before those of `netrc_file`.
+    /// A credential whose secret cannot be read is skipped with a warning.
+    pub fn resolve_credentials(&self) -> Vec<ResolvedCredential> {
+        let mut res = vec![];
+        for item in &self.credentials {
+            match item.resolve() {
+                Ok(auth) => res.push(ResolvedCredential {
+                    host: item.host.clone(),
+                    subdomains: true,
+                    auth,
+                }),
+                Err(err) => warn!("skip credential of {}: {err}", item.host),
+            }
+        }
+
+        if let Some(path) = &self.netrc_file {
+            match std::fs::read_to_string(path) {
+                Ok(content) => res.extend(parse_netrc(&content).into_iter().map(|(host, auth)| {
+                    ResolvedCredential {
+                        host,
+                        subdomains: false,
+                        auth,
+                    }
+                })),
+                Err(err) => warn!("read {} failed: {err}", path.display()),
+            }
+        }
+
+        res
+    }
+
+    pub fn credential_for(&self, url: &str) -> Option<&Credential> {
+        self.credentials
+            .iter()
+            .find(|item| host_matches(&item.host, url))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthScheme {
+    #[default]
+    Basic,
+    Bearer,
+}
+
+/// Credential of a private gallery or asset host. The secret is read from
+/// `token_env`, or from `token_file` if the variable is unset.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+#[serde(default)]
+pub struct Credential {
+    /// Also matches its subdomains.
+    pub host: String,
+    pub scheme: AuthScheme,
+    /// User of basic auth, Azure DevOps takes any name together with a PAT.
+    pub username: Option<String>,
+    pub token_env: Option<String>,
+    pub token_file: Option<PathBuf>,
+    /// Let the generated `fetchurl` authenticate through `netrcPhase`, which
+    /// needs `token_env` in the environment of the builder.
+    pub netrc_phase: bool,
+}
+
+impl Credential {
+    pub fn resolve(&self) -> anyhow::Result<Auth> {
+        let token = match (&self.token_env, &self.token_file) {
+            (Some(key), _) if std::env::var_os(key).is_some() => std::env::var(key)?,
+            (_, Some(path)) => std::fs::read_to_string(path)?.trim().to_owned(),
+            (Some(key), None) => return Err(anyhow!("{key} is not set")),
+            (None, None) => return Err(anyhow!("neither token_env nor token_file is set")),
+        };
+
+        Ok(match self.scheme {
+            AuthScheme::Basic => Auth::Basic {
+                username: self.username.clone().unwrap_or_default(),
+                password: token,
+            },
+            AuthScheme::Bearer => Auth::Bearer(token),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Auth {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+/// The lowercase host name of `url`.
+pub fn url_host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+}
+
+/// A secret of [`NetworkConfig::resolve_credentials`] and where it is sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedCredential {
+    pub host: String,
+    /// Also sent to subdomains of `host`, netrc `machine` names are exact.
+    pub subdomains: bool,
+    pub auth: Auth,
+}
+
+impl ResolvedCredential {
+    pub fn matches(&self, url: &str) -> bool {
+        match self.subdomains {
+            true => host_matches(&self.host, url),
+            false => url_host(url).is_some_and(|host| host == self.host.to_lowercase()),
+        }
+    }
+}
+
+/// Whether `url` points to `host` or one of its subdomains.
+pub fn host_matches(host: &str, url: &str) -> bool {
+    let Some(url_host) = url_host(url) else {
+        return false;
+    };
+    let host = host.to_lowercase();
+    url_host == host || url_host.ends_with(&format!(".{host}"))
+}
+
+/// `machine` entries of a netrc file, `default` and `macdef` are not supported.
+pub fn parse_netrc(content: &str) -> Vec<(String, Auth)> {
+    let mut res = vec![];
+    let mut tokens = content.split_whitespace();
+    let mut current: Option<(String, String, String)> = None;
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => {
+                res.extend(
+                    current.take().map(|(host, username, password)| {
+                        (host, Auth::Basic { username, password })
+                    }),
+                );
+                current = tokens
+                    .next()
+                    .map(|host| (host.into(), "".into(), "".into()));
+            }
+            "login" => {
+                if let (Some(item), Some(value)) = (current.as_mut(), tokens.next()) {
+                    item.1 = value.into();
+                }
+            }
+            "password" => {
+                if let (Some(item), Some(value)) = (current.as_mut(), tokens.next()) {
+                    item.2 = value.into();
+                }
+            }
+            _ => {}
+        }
+    }
+    res.extend(
+        current.map(|(host, username, password)| (host, Auth::Basic { username, password })),
+    );
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_credentials() {
+        assert!(host_matches(
+            "dev.azure.com",
+            "https://pkgs.dev.azure.com/corp/_packaging/feed/foo.vsix"
+        ));
+        assert!(host_matches("Example.com", "https://example.com/foo.vsix"));
+        assert!(!host_matches(
+            "example.com",
+            "https://notexample.com/foo.vsix"
+        ));
+
+        let netrc = r#"
+machine example.com
+  login alice
+  password secret
+machine other.com login bob password hunter2
+"#;
+        assert_eq!(
+            parse_netrc(netrc),
+            vec![
+                (
+                    "example.com".into(),
+                    Auth::Basic {
+                        username: "alice".into(),
+                        password: "secret".into()
+                    }
+                ),
+                (
+                    "other.com".into(),
+                    Auth::Basic {
+                        username: "bob".into(),
+                        password: "hunter2".into()
+                    }
+                ),
+            ]
+        );
+
+        let path = std::env::temp_dir().join(format!("nix4vscode-netrc-{}", std::process::id()));
+        std::fs::write(&path, netrc).unwrap();
+        let network = NetworkConfig {
+            credentials: vec![Credential {
+                host: "dev.azure.com".into(),
+                token_env: Some("PATH".into()),
+                ..Default::default()
+            }],
+            netrc_file: Some(path.clone()),
+            ..Default::default()
+        };
+        let resolved = network.resolve_credentials();
+        std::fs::remove_file(path).unwrap();
+        let find = |url: &str| resolved.iter().find(|item| item.matches(url));
+        assert!(find("https://pkgs.dev.azure.com/feed/foo.vsix").is_some());
+        assert!(find("https://example.com/foo.vsix").is_some());
+        assert!(find("https://cdn.example.com/foo.vsix").is_none());
+    }
+}
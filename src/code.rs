@@ -1,50 +1,106 @@
+mod policy;
+mod suggest;
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
 use std::pin::pin;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
 
-use code_api::code::is_version_valid;
 use code_api::code::AssetType;
 use code_api::code::HttpClient;
 use code_api::code::IRawGalleryExtension;
+use code_api::code::IRawGalleryExtensionVersion;
+use code_api::code::PropertyType;
 use code_api::code::TargetPlatform;
-use futures::future::join_all;
-use futures::stream;
+use code_api::code::is_version_valid;
+use code_api::config::Extension;
 use futures::StreamExt;
+use futures::stream;
+use itertools::Itertools;
 
 use semver::Version;
 use tracing::debug;
 use tracing::error;
 use tracing::trace;
+use tracing::warn;
 
+use crate::config::ApiProposalsConfig;
 use crate::config::Config;
+use crate::config::ReportLevel;
+use crate::config::Target;
+use crate::error::Error;
 use crate::jinja::AssetUrlContext;
 use crate::jinja::Generator;
+use crate::jinja::LicenseContext;
+use crate::jinja::MetaContext;
+use crate::jinja::NativeContext;
+use crate::jinja::NetrcContext;
 use crate::jinja::NixContext;
 use crate::utils;
+use crate::utils::Hasher;
+use crate::utils::PrefetchRequest;
+use crate::utils::SignatureStatus;
+use crate::utils::VsixIdentity;
+
+/// Extensions looked at in parallel, each of them may hold a whole VSIX in memory.
+const MAX_PREFETCH: usize = 8;
 
 pub struct CodeNix {
     config: Config,
     client: HttpClient,
+    hasher: Arc<dyn Hasher>,
+    /// What an offline run could not find in the cache.
+    missing: Mutex<BTreeSet<String>>,
+    /// Downloads which are not the extension the gallery described.
+    mismatched: Mutex<BTreeSet<String>>,
+    /// Extensions without a valid signature, if that is an error.
+    unverified: Mutex<BTreeSet<String>>,
+    /// Extensions breaking a rule of `[policy]`.
+    violations: Mutex<BTreeSet<String>>,
 }
 
 impl CodeNix {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config, client: HttpClient, hasher: Arc<dyn Hasher>) -> Self {
         Self {
             config,
-            client: HttpClient::new().unwrap(),
+            client,
+            hasher,
+            missing: Default::default(),
+            mismatched: Default::default(),
+            unverified: Default::default(),
+            violations: Default::default(),
         }
     }
 
-    pub async fn get_extensions(&mut self, generator: Generator<'static>) -> Vec<NixContext> {
+    pub async fn get_extensions(
+        &mut self,
+        generator: Generator<'static>,
+    ) -> anyhow::Result<Vec<NixContext>> {
         let mut obj = vec![];
+        let mut query_failed = false;
         {
+            let offline = self.config.offline;
+            let query_failed = &mut query_failed;
             let mut iter = self
                 .client
                 .get_extension_response(self.config.handled_extensions.clone())
-                .filter_map(|item| async move {
-                    match item {
+                .filter_map(|item| {
+                    let res = match item {
                         Ok(v) => Some(v),
-                        Err(_) => None,
-                    }
+                        Err(err) if offline => {
+                            debug!("query extensions failed: {err}");
+                            *query_failed = true;
+                            None
+                        }
+                        Err(err) => {
+                            error!("query extensions failed: {err}");
+                            *query_failed = true;
+                            None
+                        }
+                    };
+                    async move { res }
                 })
                 .flat_map(|item| stream::iter(item.extensions));
 
@@ -59,16 +115,147 @@ impl CodeNix {
             }
         }
 
-        let futures: Vec<_> = obj
-            .into_iter()
+        if self.config.offline {
+            self.add_cached_extensions(&mut obj)?;
+        } else if !query_failed {
+            self.check_unknown_extensions(&obj).await?;
+        }
+
+        let res: Vec<_> = stream::iter(obj)
             .map(|item| {
                 trace!("aa");
                 let generator = generator.clone();
                 self.get_matched_versoin(item, generator)
             })
+            .buffered(MAX_PREFETCH)
+            .flat_map(stream::iter)
+            .collect()
+            .await;
+
+        let violations = self.violations.get_mut().unwrap();
+        if !violations.is_empty() {
+            return Err(Error::PolicyViolation(violations.iter().join("; ")).into());
+        }
+
+        let mismatched = self.mismatched.get_mut().unwrap();
+        if !mismatched.is_empty() {
+            return Err(Error::VsixMismatch(mismatched.iter().join("; ")).into());
+        }
+
+        let unverified = self.unverified.get_mut().unwrap();
+        if !unverified.is_empty() {
+            return Err(Error::Unverified(unverified.iter().join("; ")).into());
+        }
+
+        let missing = self.missing.get_mut().unwrap();
+        if !missing.is_empty() {
+            return Err(Error::Offline(missing.iter().join(", ")).into());
+        }
+
+        Ok(res)
+    }
+
+    /// Offline, extensions the query could not answer are looked up in every
+    /// cached response of the gallery, e.g. one written before the config changed.
+    fn add_cached_extensions(&self, obj: &mut Vec<IRawGalleryExtension>) -> anyhow::Result<()> {
+        let mut cached = utils::cached_extensions(self.config.network.gallery())?;
+        for ext in &self.config.handled_extensions {
+            let id = format!("{}.{}", ext.publisher_name, ext.extension_name);
+            if obj
+                .iter()
+                .any(|item| item.to_string().eq_ignore_ascii_case(&id))
+            {
+                continue;
+            }
+            match cached.remove(&id.to_lowercase()) {
+                Some(item) => obj.push(item),
+                None => self.add_missing(format!("{id} (gallery response)")),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_missing(&self, message: String) {
+        self.missing.lock().unwrap().insert(message);
+    }
+
+    /// Reports an extension without a valid signature, `None` if it could
+    /// not be checked.
+    fn check_signature(&self, item: &IRawGalleryExtension, status: Option<&SignatureStatus>) {
+        let level = self.config.signature.unverified;
+        match status {
+            Some(SignatureStatus::Valid { signer }) => debug!("{item} is signed by {signer}"),
+            Some(status) => match level {
+                ReportLevel::Warning => warn!("{item}: {status}"),
+                ReportLevel::Error => {
+                    error!("{item}: {status}");
+                    self.unverified
+                        .lock()
+                        .unwrap()
+                        .insert(format!("{item} ({status})"));
+                }
+            },
+            None if self.config.offline && level == ReportLevel::Error => {
+                self.add_missing(format!("{item} (signature)"));
+            }
+            None => warn!("signature of {item} is not checked"),
+        }
+    }
+
+    /// Reports the configured extensions which are missing from `found`,
+    /// together with the marketplace ids they were probably meant to be.
+    async fn check_unknown_extensions(&self, found: &[IRawGalleryExtension]) -> anyhow::Result<()> {
+        let unknown: Vec<&Extension> = self
+            .config
+            .handled_extensions
+            .iter()
+            .filter(|ext| {
+                !found.iter().any(|item| {
+                    item.publisher
+                        .publisher_name
+                        .eq_ignore_ascii_case(&ext.publisher_name)
+                        && item
+                            .extension_name
+                            .eq_ignore_ascii_case(&ext.extension_name)
+                })
+            })
             .collect();
 
-        join_all(futures).await.into_iter().flatten().collect()
+        let mut messages = vec![];
+        for ext in unknown {
+            let mut candidates = vec![];
+            for text in [&ext.publisher_name, &ext.extension_name] {
+                match self.client.search_extensions(text).await {
+                    Ok(v) => candidates.extend(v),
+                    Err(err) => debug!("search {text} failed: {err}"),
+                }
+            }
+
+            let suggestions = suggest::suggest(ext, &candidates);
+            let message = if suggestions.is_empty() {
+                format!("{}.{}", ext.publisher_name, ext.extension_name)
+            } else {
+                format!(
+                    "{}.{} (did you mean {}?)",
+                    ext.publisher_name,
+                    ext.extension_name,
+                    suggestions.join(", ")
+                )
+            };
+
+            match self.config.unknown_extension {
+                ReportLevel::Warning => warn!("unknown extension: {message}"),
+                ReportLevel::Error => error!("unknown extension: {message}"),
+            }
+            messages.push(message);
+        }
+
+        if !messages.is_empty() && self.config.unknown_extension == ReportLevel::Error {
+            return Err(Error::UnknownExtensions(messages.join("; ")).into());
+        }
+
+        Ok(())
     }
 
     pub async fn get_matched_versoin(
@@ -76,33 +263,19 @@ impl CodeNix {
         item: IRawGalleryExtension,
         generator: Generator<'static>,
     ) -> Vec<NixContext> {
-        let vscode_ver = self.config.vscode_version.as_str();
-        let mx = item
-            .versions
-            .iter()
-            .filter(|v| !v.is_pre_release_version())
-            .filter(|v| match v.get_engine() {
-                Ok(ver) => {
-                    if !is_version_valid(vscode_ver, &ver) {
-                        trace!("{ver} doesn't match {vscode_ver:?}");
-                        return false;
-                    }
-                    trace!("{} - {}", v.version, ver);
-                    true
-                }
-                Err(_) => {
-                    debug!(
-                        "Cannot get engine version for {}.{} {}",
-                        item.publisher.publisher_name, item.extension_name, v
-                    );
-                    trace!("{v:#?}");
-                    true
-                }
-            })
-            .filter_map(|item| Version::from_str(&item.version).ok())
-            .max_by(|a, b| a.cmp(b));
+        let web = self.config.target == Target::Web;
+        let item = match web {
+            true => web_versions(item),
+            false => desktop_versions(item),
+        };
+        if web && item.versions.is_empty() {
+            warn!("{item} has no web build, skip it");
+            return vec![];
+        }
 
-        let mx = mx.map(|item| item.to_string());
+        let vscode_ver = self.config.vscode_version.as_str();
+        let mx = select_version(&item, vscode_ver, &self.config.api_proposals)
+            .map(|item| item.to_string());
         trace!(?mx);
 
         let mut res = vec![];
@@ -164,16 +337,76 @@ impl CodeNix {
                 version.target_platform
             );
 
-            let sha256 = match utils::get_sha256(&asset_url).await {
-                Ok(sha256) => sha256,
+            let violations = policy::violations(&self.config.policy, &item, version, &asset_url);
+            if !violations.is_empty() {
+                let message = format!("{item}@{} ({})", version.version, violations.join(", "));
+                error!("policy violation: {message}");
+                self.violations.lock().unwrap().insert(message);
+                continue;
+            }
+
+            let expected = self.config.verify_vsix.then(|| VsixIdentity {
+                publisher: item.publisher.publisher_name.clone(),
+                name: item.extension_name.clone(),
+                version: version.version.clone(),
+                engine: version.get_engine().ok(),
+            });
+            // the signature belongs to the marketplace VSIX only
+            let verify_signature = self.config.signature.verify && !has_asset_url;
+            let request = PrefetchRequest {
+                url: asset_url.clone(),
+                expected,
+                signature_url: verify_signature
+                    .then(|| version.get_file(AssetType::Signature))
+                    .flatten()
+                    .map(|file| file.source.clone()),
+            };
+            let prefetched = match self.hasher.prefetch(&request).await {
+                Ok(prefetched) => prefetched,
                 Err(err) => {
-                    error!("get sha256 failed: {err}");
+                    if let Some(Error::VsixMismatch(reason)) = err.downcast_ref() {
+                        error!("{asset_url}: {reason}");
+                        self.mismatched
+                            .lock()
+                            .unwrap()
+                            .insert(format!("{item} from {asset_url}, {reason}"));
+                    } else if self.config.offline {
+                        debug!("get sha256 failed: {err}");
+                        self.add_missing(format!("{item} (sha256 of {asset_url})"));
+                    } else {
+                        error!("get sha256 failed: {err}");
+                    }
                     continue;
                 }
             };
 
+            if self.config.split_sides && prefetched.extension_kind.is_empty() {
+                warn!("extension kind of {item} is unknown, put it on both sides");
+            }
+
+            let sha256 = prefetched.sha256;
+            let native = NativeContext::new(&prefetched.native);
+            let license = prefetched.license.as_deref().map(LicenseContext::new);
+            let meta = MetaContext::new(&item, version);
+            let signature = match (verify_signature, &request.signature_url) {
+                (false, _) => None,
+                (true, None) => Some(SignatureStatus::Unsigned),
+                (true, Some(_)) => prefetched.signature,
+            };
+            if verify_signature {
+                self.check_signature(&item, signature.as_ref());
+            }
+
+            let netrc = self
+                .config
+                .network
+                .credential_for(&asset_url)
+                .and_then(|credential| NetrcContext::new(credential, &asset_url));
+
             let target_platform = match version.target_platform {
                 Some(ref t) => vec![t.as_str().into()],
+                // a web extension runs wherever the browser does
+                None if web => vec![TargetPlatform::Universal],
                 None => {
                     self.client
                         .get_extension_target_platform(
@@ -184,6 +417,9 @@ impl CodeNix {
                 }
             };
             trace!(?target_platform);
+            if target_platform.is_empty() && self.config.offline {
+                self.add_missing(format!("{item} (target platforms)"));
+            }
 
             let a = target_platform
                 .into_iter()
@@ -209,6 +445,19 @@ impl CodeNix {
                     },
                     sha256: sha256.clone(),
                     platform: target_platform,
+                    source_url: asset_url.clone(),
+                    // the marketplace fetcher cannot authenticate
+                    vsix_url: if !has_asset_url && netrc.is_some() {
+                        Some(asset_url.clone())
+                    } else {
+                        None
+                    },
+                    netrc: netrc.clone(),
+                    native: native.clone(),
+                    meta: meta.clone(),
+                    license: license.clone(),
+                    extension_kind: prefetched.extension_kind.clone(),
+                    signature: signature.clone(),
                 });
 
             res.extend(a);
@@ -219,3 +468,371 @@ impl CodeNix {
         res
     }
 }
+
+/// One context per extension and platform.
+pub fn dedup(ctx: Vec<NixContext>) -> Vec<NixContext> {
+    let mut res = HashMap::<String, NixContext>::new();
+    for item in ctx {
+        res.insert(
+            format!("{}-{}-{:?}", item.publisher, item.name, item.platform),
+            item,
+        );
+    }
+    res.into_values().collect()
+}
+
+/// The newest stable version of `item` which can be installed into `vscode_ver`.
+pub fn get_latest_compatible_version(
+    item: &IRawGalleryExtension,
+    vscode_ver: &str,
+) -> Option<Version> {
+    item.versions
+        .iter()
+        .filter(|v| !v.is_pre_release_version())
+        .filter(|v| is_compatible(item, v, vscode_ver))
+        .filter_map(|item| Version::from_str(&item.version).ok())
+        .max_by(|a, b| a.cmp(b))
+}
+
+/// `item` with only the versions which run in a browser, builds for the
+/// `web` target and universal ones marked as web extension.
+fn web_versions(mut item: IRawGalleryExtension) -> IRawGalleryExtension {
+    item.versions.retain(|version| {
+        let target_platform: TargetPlatform = version
+            .target_platform
+            .as_deref()
+            .unwrap_or("universal")
+            .into();
+        match target_platform {
+            TargetPlatform::Web => true,
+            TargetPlatform::Universal => {
+                version.get_property(PropertyType::WEB_EXTENSION) == Some("true")
+            }
+            _ => false,
+        }
+    });
+    item
+}
+
+/// Only the versions a desktop VS Code can install, web builds have no place
+/// in its output.
+fn desktop_versions(mut item: IRawGalleryExtension) -> IRawGalleryExtension {
+    item.versions.retain(|version| {
+        version.target_platform.as_deref().map(TargetPlatform::from) != Some(TargetPlatform::Web)
+    });
+    item
+}
+
+/// The latest compatible version of `item`, or with `prefer_without` of
+/// `config` the newest one which does not rely on proposed APIs that VS Code
+/// will not enable for it.
+fn select_version(
+    item: &IRawGalleryExtension,
+    vscode_ver: &str,
+    config: &ApiProposalsConfig,
+) -> Option<Version> {
+    let latest = get_latest_compatible_version(item, vscode_ver)?;
+    if config
+        .enabled
+        .iter()
+        .any(|id| id.eq_ignore_ascii_case(&item.to_string()))
+    {
+        return Some(latest);
+    }
+
+    let proposals = |version: &Version| {
+        let version = version.to_string();
+        item.versions
+            .iter()
+            .filter(|v| v.version == version)
+            .flat_map(|v| v.get_api_proposals())
+            .unique()
+            .join(", ")
+    };
+    let used = proposals(&latest);
+    if used.is_empty() {
+        return Some(latest);
+    }
+
+    if config.prefer_without {
+        let older = item
+            .versions
+            .iter()
+            .filter(|v| !v.is_pre_release_version())
+            .filter(|v| is_compatible(item, v, vscode_ver))
+            .filter_map(|v| Version::from_str(&v.version).ok())
+            .filter(|v| proposals(v).is_empty())
+            .max();
+        match older {
+            Some(older) => {
+                warn!("{item}@{latest} uses proposed APIs ({used}), pick {older} instead");
+                return Some(older);
+            }
+            None => warn!("every compatible version of {item} uses proposed APIs"),
+        }
+    }
+    warn!(
+        "{item}@{latest} uses proposed APIs ({used}), which stable VS Code only enables for extensions its product.json lists"
+    );
+    Some(latest)
+}
+
+/// Versions without an engine requirement are treated as compatible.
+pub fn is_compatible(
+    item: &IRawGalleryExtension,
+    version: &IRawGalleryExtensionVersion,
+    vscode_ver: &str,
+) -> bool {
+    match version.get_engine() {
+        Ok(ver) => {
+            if !is_version_valid(vscode_ver, &ver) {
+                trace!("{ver} doesn't match {vscode_ver:?}");
+                return false;
+            }
+            trace!("{} - {}", version.version, ver);
+            true
+        }
+        Err(_) => {
+            debug!(
+                "Cannot get engine version for {}.{} {}",
+                item.publisher.publisher_name, item.extension_name, version
+            );
+            trace!("{version:#?}");
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use code_api::code::{
+        FixtureBackend, IRawGalleryExtensionProperty, IRawGalleryExtensionPublisher,
+    };
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::jinja::GeneratorContext;
+    use crate::utils::{FixtureHasher, GALLERY_FIXTURE, SHA256_FIXTURE};
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/resolve");
+
+    /// Resolves `config` against the recorded marketplace.
+    async fn get_extensions(config: &Config) -> anyhow::Result<Vec<NixContext>> {
+        let dir = std::path::Path::new(FIXTURES);
+        let client = HttpClient::new().unwrap().with_backend(Arc::new(
+            FixtureBackend::replay(dir.join(GALLERY_FIXTURE)).unwrap(),
+        ));
+        let hasher = Arc::new(FixtureHasher::replay(dir.join(SHA256_FIXTURE)).unwrap());
+
+        let mut code = CodeNix::new(config.clone(), client, hasher);
+        code.get_extensions(Generator::new()).await
+    }
+
+    /// Resolves and renders `config` against the recorded marketplace.
+    async fn resolve(config: &str) -> (Vec<String>, String) {
+        let config = Config::from_file(&format!("{FIXTURES}/{config}"))
+            .await
+            .unwrap();
+        let mut generator = Generator::new();
+        let extensions = get_extensions(&config).await.unwrap();
+
+        let ids = extensions
+            .iter()
+            .map(|item| {
+                format!(
+                    "{}.{}@{}:{:?}",
+                    item.publisher, item.name, item.version, item.platform
+                )
+            })
+            .sorted()
+            .collect_vec();
+        let res = generator
+            .render(&GeneratorContext::new(config, extensions))
+            .unwrap();
+
+        (ids, res)
+    }
+
+    #[tokio::test]
+    async fn test_resolve_fixtures() {
+        let (ids, res) = resolve("config.toml").await;
+        assert_eq!(
+            ids,
+            [
+                "foo.native@1.0.0:DarwinArm64",
+                "foo.native@1.0.0:LinuxX64",
+                "foo.universal@1.1.0:Universal",
+            ]
+        );
+        assert!(res.contains(r#""foo"."universal" = vscode-utils.extensionFromVscodeMarketplace"#));
+        assert!(res.contains("0a5gcmb7zmvkf5lglb1x2jdx8q2n6l9h3kqvhz4n0w1nq3ihfjz1"));
+        assert!(res.contains(r#"arch = "darwin-arm64";"#));
+        assert!(!res.contains("netrcPhase"));
+        assert!(res.contains(r#"description = "The universal fixture";"#));
+        assert!(res.contains(r#"publisherDisplayName = "Foo";"#));
+        // internal gallery tags are dropped
+        assert!(!res.contains("__web_extension"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_profile_fixtures() {
+        let config = Config::from_file(&format!("{FIXTURES}/profiles.toml"))
+            .await
+            .unwrap();
+        let ctx = GeneratorContext::new(config.clone(), get_extensions(&config).await.unwrap());
+        let names = |profile: &str| {
+            ctx.profiles[profile]
+                .iter()
+                .map(|item| format!("{}.{}", item.publisher, item.name))
+                .unique()
+                .sorted()
+                .collect_vec()
+        };
+        assert_eq!(names("base"), ["foo.universal"]);
+        assert_eq!(names("native"), ["foo.native", "foo.universal"]);
+
+        let res = Generator::new().render(&ctx).unwrap();
+        assert!(res.contains(r#""base" = merge"#));
+        assert!(res.contains(r#""native" = merge"#));
+        let res = Generator::new()
+            .render(&ctx.profile("base").unwrap())
+            .unwrap();
+        assert!(!res.contains("native"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_private_fixtures() {
+        let (_, res) = resolve("private.toml").await;
+        assert!(res.contains("vscode-utils.buildVscodeMarketplaceExtension"));
+        assert!(!res.contains("extensionFromVscodeMarketplace"));
+        assert!(res.contains(r#"arch = "darwin-arm64";"#));
+        assert!(res.contains(r#"netrcImpureEnvVars = [ "FOO_FEED_TOKEN" ];"#));
+        assert!(
+            res.contains(
+                "machine foo.gallery.vsassets.io login nix4vscode password $FOO_FEED_TOKEN"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_unsigned_fixtures() {
+        let config = Config::from_file(&format!("{FIXTURES}/unsigned.toml"))
+            .await
+            .unwrap();
+        let err = get_extensions(&config).await.unwrap_err();
+        let Some(Error::Unverified(message)) = err.downcast_ref() else {
+            panic!("{err}");
+        };
+        assert!(message.contains("foo.native (unsigned)"));
+        assert!(message.contains("foo.universal (unsigned)"));
+    }
+
+    #[test]
+    fn test_select_version() {
+        let version = |version: &str, proposals: Option<&str>| IRawGalleryExtensionVersion {
+            version: version.into(),
+            properties: [
+                (PropertyType::ENGINE, Some("^1.80.0")),
+                (PropertyType::ENABLED_API_PROPOSALS, proposals),
+            ]
+            .into_iter()
+            .filter_map(|(key, value)| {
+                value.map(|value| IRawGalleryExtensionProperty {
+                    key: key.into(),
+                    value: value.into(),
+                })
+            })
+            .collect(),
+            ..Default::default()
+        };
+        let item = IRawGalleryExtension {
+            extension_name: "chat".into(),
+            publisher: IRawGalleryExtensionPublisher {
+                publisher_name: "foo".into(),
+                ..Default::default()
+            },
+            versions: vec![
+                version("1.2.0", Some("chatParticipant, languageModels")),
+                version("1.1.0", None),
+                version("1.0.0", Some("")),
+            ],
+            ..Default::default()
+        };
+        let select = |enabled: &[&str], prefer_without: bool| {
+            let config = ApiProposalsConfig {
+                enabled: enabled.iter().map(|id| id.to_string()).collect(),
+                prefer_without,
+            };
+            select_version(&item, "1.90.0", &config)
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(select(&[], false), "1.2.0");
+        assert_eq!(select(&[], true), "1.1.0");
+        assert_eq!(select(&["Foo.Chat"], true), "1.2.0");
+    }
+
+    #[test]
+    fn test_desktop_versions() {
+        let version = |target_platform: Option<&str>| IRawGalleryExtensionVersion {
+            version: "1.0.0".into(),
+            target_platform: target_platform.map(str::to_owned),
+            ..Default::default()
+        };
+        let item = IRawGalleryExtension {
+            versions: vec![
+                version(Some("web")),
+                version(Some("linux-x64")),
+                version(None),
+            ],
+            ..Default::default()
+        };
+
+        let platforms: Vec<_> = desktop_versions(item)
+            .versions
+            .iter()
+            .map(|version| version.target_platform.clone())
+            .collect();
+        assert_eq!(platforms, [Some("linux-x64".to_owned()), None]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_web_fixtures() {
+        let (ids, res) = resolve("web.toml").await;
+        assert_eq!(
+            ids,
+            ["foo.native@1.0.0:Web", "foo.universal@1.1.0:Universal"]
+        );
+        assert!(res.contains(r#"arch = "web";"#));
+        assert!(!res.contains("linux-x64"));
+
+        // desktop output has no place for web builds
+        let mut config = Config::from_file(&format!("{FIXTURES}/web.toml"))
+            .await
+            .unwrap();
+        config.target = Target::Desktop;
+        let extensions = get_extensions(&config).await.unwrap();
+        assert!(!extensions.is_empty());
+        assert!(
+            extensions
+                .iter()
+                .all(|item| item.platform != TargetPlatform::Web)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_policy_fixtures() {
+        let config = Config::from_file(&format!("{FIXTURES}/policy.toml"))
+            .await
+            .unwrap();
+        let err = get_extensions(&config).await.unwrap_err();
+        let Some(Error::PolicyViolation(message)) = err.downcast_ref() else {
+            panic!("{err}");
+        };
+        assert!(message.contains(
+            r#"foo.native@1.0.0 (denied by "foo.native", publisher foo has no verified domain)"#
+        ));
+        assert!(message.contains("foo.universal@1.1.0 (publisher foo has no verified domain)"));
+    }
+}
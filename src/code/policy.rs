@@ -0,0 +1,131 @@
+use code_api::code::{IRawGalleryExtension, IRawGalleryExtensionVersion};
+use code_api::config::host_matches;
+
+use crate::config::PolicyConfig;
+
+/// Whether `pattern`, a publisher or `publisher.name`, matches `item`.
+fn matches(pattern: &str, item: &IRawGalleryExtension) -> bool {
+    match pattern.split_once('.') {
+        Some(_) => pattern.eq_ignore_ascii_case(&item.to_string()),
+        None => pattern.eq_ignore_ascii_case(&item.publisher.publisher_name),
+    }
+}
+
+/// Every rule of `policy` which `version` of `item`, downloaded from
+/// `asset_url`, breaks.
+pub fn violations(
+    policy: &PolicyConfig,
+    item: &IRawGalleryExtension,
+    version: &IRawGalleryExtensionVersion,
+    asset_url: &str,
+) -> Vec<String> {
+    let mut res = vec![];
+    if let Some(pattern) = policy.deny.iter().find(|pattern| matches(pattern, item)) {
+        res.push(format!("denied by {pattern:?}"));
+    }
+    if !policy.allow.is_empty() && !policy.allow.iter().any(|pattern| matches(pattern, item)) {
+        res.push("not allowed".into());
+    }
+    if policy.verified_publisher && !item.publisher.is_domain_verified {
+        res.push(format!(
+            "publisher {} has no verified domain",
+            item.publisher.publisher_name
+        ));
+    }
+
+    if let Some(min) = policy.min_installs {
+        match item.get_statistic(IRawGalleryExtension::STATISTIC_INSTALL) {
+            Some(installs) if installs >= min as f64 => {}
+            Some(installs) => res.push(format!("{installs} installs, {min} required")),
+            None => res.push("install count unknown".into()),
+        }
+    }
+    if let Some(min) = policy.min_rating {
+        match item.get_statistic(IRawGalleryExtension::STATISTIC_AVERAGE_RATING) {
+            Some(rating) if rating >= min => {}
+            Some(rating) => res.push(format!("rated {rating:.1}, {min} required")),
+            None => res.push("rating unknown".into()),
+        }
+    }
+
+    let proposals = version.get_api_proposals();
+    if policy.deny_api_proposals && !proposals.is_empty() {
+        res.push(format!("uses proposed APIs {}", proposals.join(",")));
+    }
+    if !policy.asset_domains.is_empty()
+        && !policy
+            .asset_domains
+            .iter()
+            .any(|domain| host_matches(domain, asset_url))
+    {
+        res.push(format!("{asset_url} is not on an allowed domain"));
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use code_api::code::{
+        IRawGalleryExtensionProperty, IRawGalleryExtensionPublisher,
+        IRawGalleryExtensionStatistics, PropertyType,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_violations() {
+        let item = IRawGalleryExtension {
+            extension_name: "python".into(),
+            publisher: IRawGalleryExtensionPublisher {
+                publisher_name: "ms-python".into(),
+                is_domain_verified: true,
+                ..Default::default()
+            },
+            statistics: vec![IRawGalleryExtensionStatistics {
+                statistic_name: IRawGalleryExtension::STATISTIC_INSTALL.into(),
+                value: 1000.0,
+            }],
+            ..Default::default()
+        };
+        let version = IRawGalleryExtensionVersion {
+            properties: vec![IRawGalleryExtensionProperty {
+                key: PropertyType::ENABLED_API_PROPOSALS.into(),
+                value: "terminalDataWriteEvent".into(),
+            }],
+            ..Default::default()
+        };
+        let url = "https://ms-python.gallery.vsassets.io/python.vsix";
+
+        assert!(violations(&Default::default(), &item, &version, url).is_empty());
+        let policy = PolicyConfig {
+            allow: vec!["MS-Python".into()],
+            verified_publisher: true,
+            min_installs: Some(100),
+            asset_domains: vec!["vsassets.io".into()],
+            ..Default::default()
+        };
+        assert!(violations(&policy, &item, &version, url).is_empty());
+
+        let policy = PolicyConfig {
+            allow: vec!["redhat".into()],
+            deny: vec!["ms-python.python".into()],
+            min_installs: Some(5000),
+            min_rating: Some(4.0),
+            deny_api_proposals: true,
+            asset_domains: vec!["example.com".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            violations(&policy, &item, &version, url),
+            [
+                r#"denied by "ms-python.python""#,
+                "not allowed",
+                "1000 installs, 5000 required",
+                "rating unknown",
+                "uses proposed APIs terminalDataWriteEvent",
+                "https://ms-python.gallery.vsassets.io/python.vsix is not on an allowed domain",
+            ]
+        );
+    }
+}
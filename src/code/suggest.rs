@@ -0,0 +1,112 @@
+use code_api::code::IRawGalleryExtension;
+use code_api::config::Extension;
+use itertools::Itertools;
+
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Pick the marketplace ids which look like what the user meant to write.
+pub fn suggest(requested: &Extension, candidates: &[IRawGalleryExtension]) -> Vec<String> {
+    let publisher = requested.publisher_name.to_lowercase();
+    let name = requested.extension_name.to_lowercase();
+    let id = format!("{publisher}.{name}");
+
+    candidates
+        .iter()
+        .filter_map(|item| {
+            let candidate_publisher = item.publisher.publisher_name.to_lowercase();
+            let candidate_name = item.extension_name.to_lowercase();
+
+            let score = if candidate_publisher == name && candidate_name == publisher {
+                // publisher and name are swapped
+                0
+            } else if candidate_publisher == publisher && candidate_name == name {
+                // only the case differs
+                0
+            } else if candidate_name == name || candidate_publisher == publisher {
+                1 + levenshtein(&id, &format!("{candidate_publisher}.{candidate_name}"))
+            } else {
+                let distance = levenshtein(&id, &format!("{candidate_publisher}.{candidate_name}"));
+                if distance > (id.len() / 4).max(2) {
+                    return None;
+                }
+                1 + distance
+            };
+
+            Some((score, item.to_string()))
+        })
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, id)| id)
+        .unique()
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect_vec();
+    let mut prev = (0..=b.len()).collect_vec();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use code_api::code::IRawGalleryExtensionPublisher;
+
+    use super::*;
+
+    fn extension(publisher_name: &str, extension_name: &str) -> IRawGalleryExtension {
+        IRawGalleryExtension {
+            extension_name: extension_name.into(),
+            publisher: IRawGalleryExtensionPublisher {
+                publisher_name: publisher_name.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn requested(publisher_name: &str, extension_name: &str) -> Extension {
+        Extension {
+            publisher_name: publisher_name.into(),
+            extension_name: extension_name.into(),
+            asset_url: None,
+            system: None,
+        }
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("debugy", "debugpy"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let candidates = [
+            extension("ms-python", "python"),
+            extension("ms-python", "debugpy"),
+            extension("someone", "unrelated"),
+        ];
+
+        assert_eq!(
+            suggest(&requested("debugpy", "ms-python"), &candidates),
+            vec!["ms-python.debugpy"]
+        );
+        assert_eq!(
+            suggest(&requested("ms-python", "debugy"), &candidates)[0],
+            "ms-python.debugpy"
+        );
+        assert!(suggest(&requested("foo", "bar"), &candidates).is_empty());
+    }
+}
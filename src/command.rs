@@ -0,0 +1,123 @@
+mod cache;
+mod info;
+mod mirror;
+mod outdated;
+mod search;
+
+use std::path::Path;
+use std::time::Duration;
+
+use clap::Subcommand;
+use code_api::code::HttpClient;
+use itertools::Itertools;
+
+use crate::config::{Config, normalize_vscode_version};
+use crate::error::Error;
+use crate::utils;
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Search extensions in the marketplace
+    Search(search::SearchArgs),
+    /// Show an extension and all of its versions
+    Info(info::InfoArgs),
+    /// Compare pinned extensions against the marketplace
+    Outdated(outdated::OutdatedArgs),
+    /// Download the VSIX files into a directory and render a nix expression using it
+    Mirror(mirror::MirrorArgs),
+    /// Manage the local cache of hashes and gallery responses
+    Cache(cache::CacheArgs),
+}
+
+impl Command {
+    /// `offline` answers gallery queries from the cache only.
+    pub async fn run(self, offline: bool) -> anyhow::Result<()> {
+        match self {
+            Self::Search(args) => search::run(args, offline).await,
+            Self::Info(args) => info::run(args, offline).await,
+            Self::Outdated(args) => outdated::run(args, offline).await,
+            Self::Mirror(args) => mirror::run(args, offline).await,
+            Self::Cache(args) => cache::run(args).await,
+        }
+    }
+
+    /// The config files the subcommand reads, later ones layered over the
+    /// earlier ones.
+    pub fn config(&self) -> &[String] {
+        match self {
+            Self::Search(args) => std::slice::from_ref(&args.config),
+            Self::Info(args) => std::slice::from_ref(&args.config),
+            Self::Outdated(args) => std::slice::from_ref(&args.config),
+            Self::Mirror(args) => &args.config,
+            Self::Cache(_) => &[],
+        }
+    }
+}
+
+/// `configs` if one of them exists, subcommands work without one.
+pub async fn load_config(configs: &[impl AsRef<Path>]) -> anyhow::Result<Option<Config>> {
+    match configs.iter().any(|config| config.as_ref().exists()) {
+        true => Ok(Some(Config::from_files(configs).await?)),
+        false => Ok(None),
+    }
+}
+
+/// `vscode_version` wins over the one in `config`, which is optional.
+fn get_vscode_version(
+    config: Option<&Config>,
+    vscode_version: Option<&str>,
+) -> anyhow::Result<Option<String>> {
+    match vscode_version {
+        Some(v) => Ok(Some(normalize_vscode_version(v)?)),
+        None => Ok(config.map(|config| config.vscode_version.clone())),
+    }
+}
+
+/// `offline` of the command line, or of `config`.
+fn is_offline(config: Option<&Config>, offline: bool) -> bool {
+    offline || config.is_some_and(|config| config.offline)
+}
+
+/// A gallery client following the `[network]` section and `http_cache_ttl`
+/// of `config`.
+fn http_client(config: Option<&Config>, offline: bool) -> anyhow::Result<HttpClient> {
+    let default = Config::default();
+    let config = config.unwrap_or(&default);
+    utils::http_client(
+        Duration::from_secs(config.http_cache_ttl),
+        offline,
+        &config.network,
+    )
+}
+
+/// The publisher and the name of a `publisher.name` id.
+fn split_extension_id(id: &str) -> Result<(&str, &str), Error> {
+    match id.split_once('.') {
+        Some((publisher, name)) if !publisher.is_empty() && !name.is_empty() => {
+            Ok((publisher, name))
+        }
+        _ => Err(Error::InvalidExtensionId(id.into())),
+    }
+}
+
+fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let header = header.map(String::from);
+    let widths: Vec<usize> = (0..N)
+        .map(|col| {
+            std::iter::once(&header)
+                .chain(rows)
+                .map(|row| row[col].len())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    for row in std::iter::once(&header).chain(rows) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
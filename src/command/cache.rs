@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::*;
+
+use crate::utils::{CacheType, GLOBAL_CACHER, cached_at, now};
+
+#[derive(Debug, Args)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    command: CacheCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheCommand {
+    /// Show the location and the number of entries of the cache
+    Stats,
+    /// List cached entries
+    List {
+        #[arg(long = "type", value_enum)]
+        cache_type: Option<CacheType>,
+    },
+    /// Remove all entries
+    Clear {
+        #[arg(long = "type", value_enum)]
+        cache_type: Option<CacheType>,
+    },
+    /// Remove the entries of an url or of an extension id like ms-python.debugpy
+    Evict { target: String },
+    /// Write entries to a json file
+    Export {
+        file: String,
+        #[arg(long = "type", value_enum)]
+        cache_type: Option<CacheType>,
+    },
+    /// Read entries written by `export`
+    Import { file: String },
+}
+
+/// The file format of `export` and `import`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct CacheFile {
+    sha256: BTreeMap<String, String>,
+    http: BTreeMap<String, String>,
+    vsix: BTreeMap<String, String>,
+}
+
+impl CacheFile {
+    fn get_mut(&mut self, cache_type: CacheType) -> &mut BTreeMap<String, String> {
+        match cache_type {
+            CacheType::Cache256 => &mut self.sha256,
+            CacheType::HttpClient => &mut self.http,
+            CacheType::Vsix => &mut self.vsix,
+        }
+    }
+}
+
+fn selected(cache_type: Option<CacheType>) -> Vec<CacheType> {
+    match cache_type {
+        Some(v) => vec![v],
+        None => CacheType::ALL.to_vec(),
+    }
+}
+
+pub async fn run(args: CacheArgs) -> anyhow::Result<()> {
+    match args.command {
+        CacheCommand::Stats => {
+            match GLOBAL_CACHER.path() {
+                Some(path) => {
+                    let size = fs::metadata(path)
+                        .await
+                        .map(|item| item.len())
+                        .unwrap_or_default();
+                    println!("path:    {}", path.display());
+                    println!("size:    {size} bytes");
+                }
+                None => println!("path:    (in memory)"),
+            }
+            println!("schema:  {}", GLOBAL_CACHER.schema_version()?);
+            if GLOBAL_CACHER.is_read_only() {
+                println!("mode:    read-only");
+            }
+            println!(
+                "sha256:  {} entries",
+                GLOBAL_CACHER.len(CacheType::Cache256)?
+            );
+            println!(
+                "http:    {} entries",
+                GLOBAL_CACHER.len(CacheType::HttpClient)?
+            );
+            println!("vsix:    {} entries", GLOBAL_CACHER.len(CacheType::Vsix)?);
+        }
+        CacheCommand::List { cache_type } => {
+            for cache_type in selected(cache_type) {
+                for (key, value) in GLOBAL_CACHER.entries(cache_type)? {
+                    match cache_type {
+                        CacheType::Cache256 | CacheType::Vsix => println!("{value}  {key}"),
+                        CacheType::HttpClient => {
+                            let age = cached_at(&value)
+                                .map(|created| format!("{}s", now().saturating_sub(created)))
+                                .unwrap_or("-".into());
+                            println!("{age}  {key}");
+                        }
+                    }
+                }
+            }
+        }
+        CacheCommand::Clear { cache_type } => {
+            for cache_type in selected(cache_type) {
+                GLOBAL_CACHER.clear(cache_type)?;
+                info!("clear {cache_type:?}");
+            }
+        }
+        CacheCommand::Evict { target } => {
+            let matches = evict_matcher(&target);
+            let mut removed = 0;
+            for cache_type in CacheType::ALL {
+                removed += GLOBAL_CACHER.remove_if(cache_type, |key| matches(cache_type, key))?;
+            }
+            info!("remove {removed} entries of {target}");
+        }
+        CacheCommand::Export { file, cache_type } => {
+            let mut content = CacheFile::default();
+            for cache_type in selected(cache_type) {
+                content
+                    .get_mut(cache_type)
+                    .extend(GLOBAL_CACHER.entries(cache_type)?);
+            }
+            fs::write(&file, serde_json::to_string_pretty(&content)?).await?;
+            info!(
+                "export {} sha256, {} http and {} vsix entries to {file}",
+                content.sha256.len(),
+                content.http.len(),
+                content.vsix.len()
+            );
+        }
+        CacheCommand::Import { file } => {
+            let content: CacheFile = serde_json::from_str(&fs::read_to_string(&file).await?)?;
+            GLOBAL_CACHER.insert_all(
+                [
+                    (CacheType::Cache256, &content.sha256),
+                    (CacheType::HttpClient, &content.http),
+                    (CacheType::Vsix, &content.vsix),
+                ]
+                .into_iter()
+                .flat_map(|(cache_type, entries)| {
+                    entries
+                        .iter()
+                        .map(move |(key, value)| (cache_type, key.as_str(), value.as_str()))
+                }),
+            )?;
+            info!(
+                "import {} sha256, {} http and {} vsix entries from {file}",
+                content.sha256.len(),
+                content.http.len(),
+                content.vsix.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// An url only matches itself, an extension id matches the marketplace urls
+/// of the extension and the gallery queries which ask for it.
+fn evict_matcher(target: &str) -> impl Fn(CacheType, &str) -> bool + '_ {
+    let id = match target.contains("://") {
+        true => None,
+        false => target
+            .split_once('.')
+            .map(|(publisher, name)| (publisher.to_lowercase(), name.to_lowercase())),
+    };
+
+    move |cache_type, key| match (&id, cache_type) {
+        (None, _) => key == target,
+        (Some((publisher, name)), CacheType::Cache256 | CacheType::Vsix) => {
+            let key = key.to_lowercase();
+            key.contains(&format!("/{publisher}/{name}/"))
+                || key.contains(&format!("/publisher/{publisher}/extension/{name}/"))
+        }
+        (Some((publisher, name)), CacheType::HttpClient) => key
+            .to_lowercase()
+            .contains(&format!("\"{publisher}.{name}\"")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_evict_matcher() {
+        let url = "https://ms-python.gallerycdn.vsassets.io/extensions/ms-python/debugpy/2024.0.0/1706000000000/Microsoft.VisualStudio.Services.VSIXPackage";
+        let matches = evict_matcher("MS-Python.debugpy");
+        assert!(matches(CacheType::Cache256, url));
+        assert!(!matches(
+            CacheType::Cache256,
+            "https://ms-python.gallerycdn.vsassets.io/extensions/ms-python/python/2024.0.0/1706000000000/Microsoft.VisualStudio.Services.VSIXPackage"
+        ));
+        assert!(matches(
+            CacheType::HttpClient,
+            r#"{"filters":[{"criteria":[{"filterType":7,"value":"ms-python.debugpy"}]}]}"#
+        ));
+
+        let matches = evict_matcher(url);
+        assert!(matches(CacheType::Cache256, url));
+        assert!(!matches(CacheType::Cache256, "https://example.com"));
+    }
+}
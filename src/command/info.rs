@@ -0,0 +1,115 @@
+use clap::Args;
+use code_api::code::IRawGalleryExtension;
+use itertools::Itertools;
+
+use super::{
+    get_vscode_version, http_client, is_offline, load_config, print_table, split_extension_id,
+};
+use crate::code::{get_latest_compatible_version, is_compatible};
+use crate::error::Error;
+
+#[derive(Debug, Args)]
+pub struct InfoArgs {
+    /// Extension id, e.g. ms-python.debugpy
+    id: String,
+    /// Check compatibility against the vscode_version of this config
+    #[arg(short, long, default_value = "config.toml")]
+    pub(super) config: String,
+    /// Check compatibility against this VS Code version instead
+    #[arg(long)]
+    vscode_version: Option<String>,
+}
+
+pub async fn run(args: InfoArgs, offline: bool) -> anyhow::Result<()> {
+    let (publisher_name, extension_name) = split_extension_id(&args.id)?;
+
+    let config = load_config(&[&args.config]).await?;
+    let vscode_version = get_vscode_version(config.as_ref(), args.vscode_version.as_deref())?;
+
+    let client = http_client(config.as_ref(), is_offline(config.as_ref(), offline))?;
+    let Some(item) = client
+        .get_extension_detail(publisher_name, extension_name)
+        .await?
+    else {
+        return Err(Error::UnknownExtensions(args.id).into());
+    };
+
+    print_extension(&item);
+    println!();
+    print_versions(&item, vscode_version.as_deref());
+
+    Ok(())
+}
+
+fn print_extension(item: &IRawGalleryExtension) {
+    let publisher = &item.publisher;
+    let verified = match (&publisher.domain, publisher.is_domain_verified) {
+        (Some(domain), true) => format!("verified domain {domain}"),
+        (Some(domain), false) => format!("unverified domain {domain}"),
+        (None, _) => "no domain".into(),
+    };
+
+    println!("{} ({item})", item.display_name);
+    if let Some(description) = &item.short_description {
+        println!("{description}");
+    }
+    println!();
+    println!(
+        "publisher:    {} ({}), {verified}",
+        publisher.display_name, publisher.publisher_name
+    );
+    println!(
+        "categories:   {}",
+        item.categories.iter().flatten().join(", ")
+    );
+    println!("tags:         {}", item.tags.iter().flatten().join(", "));
+    if let Some(installs) = item.get_statistic(IRawGalleryExtension::STATISTIC_INSTALL) {
+        println!("installs:     {installs:.0}");
+    }
+    if let Some(rating) = item.get_statistic(IRawGalleryExtension::STATISTIC_AVERAGE_RATING) {
+        let count = item
+            .get_statistic(IRawGalleryExtension::STATISTIC_RATING_COUNT)
+            .unwrap_or_default();
+        println!("rating:       {rating:.1} ({count:.0} ratings)");
+    }
+    println!("last updated: {}", item.last_updated);
+}
+
+fn print_versions(item: &IRawGalleryExtension, vscode_version: Option<&str>) {
+    let selected =
+        vscode_version.and_then(|v| get_latest_compatible_version(item, v).map(|v| v.to_string()));
+
+    let rows = item
+        .versions
+        .iter()
+        .map(|version| {
+            let mark = if selected.as_ref() == Some(&version.version) {
+                "*"
+            } else {
+                ""
+            };
+            [
+                format!("{}{mark}", version.version),
+                version.get_engine().unwrap_or("-".into()),
+                version.is_pre_release_version().to_string(),
+                version
+                    .target_platform
+                    .clone()
+                    .unwrap_or("universal".into()),
+                match vscode_version {
+                    Some(v) => is_compatible(item, version, v).to_string(),
+                    None => "-".into(),
+                },
+            ]
+        })
+        .collect_vec();
+
+    print_table(
+        ["VERSION", "ENGINE", "PRE-RELEASE", "PLATFORM", "COMPATIBLE"],
+        &rows,
+    );
+
+    if let (Some(vscode_version), Some(selected)) = (vscode_version, selected) {
+        println!("\n* {selected} is selected for VS Code {vscode_version}");
+    }
+}
@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use clap::Args;
+use code_api::code::{ReqwestBackend, TargetPlatform};
+use futures::{StreamExt, stream};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::*;
+
+use crate::code::{self, CodeNix};
+use crate::config::Config;
+use crate::error::Error;
+use crate::jinja::{Generator, GeneratorContext, NixContext};
+use crate::utils::{self, VsixIdentity, nix_sha256, vsix_file_name};
+
+#[derive(Debug, Args)]
+pub struct MirrorArgs {
+    /// Directory of the VSIX files, which are named by their sha256
+    dir: PathBuf,
+    /// Config file layered over the ones before it, may be repeated
+    #[arg(short, long, default_value = "config.toml")]
+    pub(super) config: Vec<String>,
+    /// URL the directory is served from, defaults to its file:// path
+    #[arg(long)]
+    base_url: Option<String>,
+    /// Where to write the nix expression, defaults to stdout
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Number of parallel downloads
+    #[arg(short, long, default_value_t = 4)]
+    jobs: usize,
+}
+
+/// An entry of `index.json` in the mirror directory.
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    publisher: String,
+    name: String,
+    version: String,
+    platform: TargetPlatform,
+    sha256: String,
+    file: String,
+    source_url: String,
+}
+
+impl IndexEntry {
+    const FILE: &str = "index.json";
+}
+
+pub async fn run(args: MirrorArgs, offline: bool) -> anyhow::Result<()> {
+    let mut config = Config::from_files(&args.config).await?;
+    config.offline |= offline;
+    fs::create_dir_all(&args.dir).await?;
+
+    let mut generator = Generator::new();
+    let client = utils::http_client(
+        Duration::from_secs(config.http_cache_ttl),
+        config.offline,
+        &config.network,
+    )?;
+    // what the hasher downloads is mirrored right away
+    let hasher = utils::hasher(&config)?.keep_in(args.dir.clone());
+    let mut code = CodeNix::new(config.clone(), client, Arc::new(hasher));
+    let mut extensions = code::dedup(code.get_extensions(generator.clone()).await?);
+
+    let base_url = match &args.base_url {
+        Some(url) => url.trim_end_matches('/').to_owned(),
+        None => format!("file://{}", fs::canonicalize(&args.dir).await?.display()),
+    };
+
+    let backend = ReqwestBackend::new(&config.network)?;
+    let failed = stream::iter(&extensions)
+        .map(|item| async {
+            store(&backend, &args.dir, item, &config)
+                .await
+                .map_err(|err| format!("{}.{}: {err}", item.publisher, item.name))
+        })
+        .buffer_unordered(args.jobs.max(1))
+        .filter_map(|res| async move { res.err() })
+        .collect::<Vec<_>>()
+        .await;
+    if !failed.is_empty() {
+        return Err(Error::MirrorFailed(failed.join("; ")).into());
+    }
+
+    let mut index = vec![];
+    for item in &mut extensions {
+        let file = file_name(item);
+        index.push(IndexEntry {
+            publisher: item.publisher.clone(),
+            name: item.name.clone(),
+            version: item.version.clone(),
+            platform: item.platform,
+            sha256: item.sha256.clone(),
+            file: file.clone(),
+            source_url: item.source_url.clone(),
+        });
+
+        let url = format!("{base_url}/{file}");
+        match item.asset_url {
+            Some(_) => item.asset_url = Some(url),
+            None => item.vsix_url = Some(url),
+        }
+        item.netrc = None;
+    }
+    index.sort_by(|a, b| (&a.publisher, &a.name, &a.file).cmp(&(&b.publisher, &b.name, &b.file)));
+    fs::write(
+        args.dir.join(IndexEntry::FILE),
+        serde_json::to_string_pretty(&index)?,
+    )
+    .await?;
+    info!(
+        "mirror {} files of {} extensions in {}",
+        index.len(),
+        index
+            .iter()
+            .unique_by(|item| (&item.publisher, &item.name))
+            .count(),
+        args.dir.display()
+    );
+
+    let res = generator.render(&GeneratorContext::new(config, extensions))?;
+    match args.output {
+        Some(filepath) => fs::write(filepath, res).await?,
+        None => println!("{res}"),
+    }
+
+    Ok(())
+}
+
+fn file_name(item: &NixContext) -> String {
+    vsix_file_name(&item.sha256)
+}
+
+/// Downloads the VSIX of `item` unless the mirror has it, and checks its hash
+/// and identity. Only VSIX whose hash was cached are not mirrored yet.
+async fn store(
+    backend: &ReqwestBackend,
+    dir: &Path,
+    item: &NixContext,
+    config: &Config,
+) -> anyhow::Result<()> {
+    let path = dir.join(file_name(item));
+    if fs::try_exists(&path).await? {
+        trace!("{} is mirrored", item.source_url);
+        return Ok(());
+    }
+    if config.offline {
+        return Err(anyhow!("{} is not mirrored yet", item.source_url));
+    }
+
+    info!("download {}", item.source_url);
+    let content = backend.download(&item.source_url).await?;
+    let sha256 = nix_sha256(&content);
+    if sha256 != item.sha256 {
+        return Err(anyhow!(
+            "sha256 of {} is {sha256} instead of {}",
+            item.source_url,
+            item.sha256
+        ));
+    }
+    if config.verify_vsix {
+        VsixIdentity {
+            publisher: item.publisher.clone(),
+            name: item.name.clone(),
+            version: item.version.clone(),
+            engine: None,
+        }
+        .verify(&content)?;
+    }
+
+    let part = path.with_extension("vsix.part");
+    fs::write(&part, content).await?;
+    fs::rename(&part, &path).await?;
+    Ok(())
+}
@@ -0,0 +1,314 @@
+mod changelog;
+
+use std::collections::BTreeMap;
+use std::pin::pin;
+use std::str::FromStr;
+
+use clap::Args;
+use code_api::code::{
+    AssetType, IRawGalleryExtension, IRawGalleryExtensionVersion, ReqwestBackend,
+};
+use code_api::config::Extension;
+use futures::StreamExt;
+use itertools::Itertools;
+use lazy_regex::regex;
+use semver::Version;
+use serde::Deserialize;
+use tokio::fs;
+use tracing::*;
+
+use self::changelog::changelog_between;
+use super::{get_vscode_version, http_client, is_offline, load_config, print_table};
+use crate::code::get_latest_compatible_version;
+use crate::error::Error;
+
+#[derive(Debug, Args)]
+pub struct OutdatedArgs {
+    /// Output of a previous run, either the nix expression or the `--export` json
+    file: String,
+    #[arg(short, long, default_value = "config.toml")]
+    pub(super) config: String,
+    #[arg(long)]
+    vscode_version: Option<String>,
+    /// Also list extensions which are up to date
+    #[arg(long)]
+    all: bool,
+    /// Write what changed in every bumped extension as markdown, `-` for stdout
+    #[arg(long)]
+    changelog: Option<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+struct Pinned {
+    publisher: String,
+    name: String,
+    version: String,
+}
+
+pub async fn run(args: OutdatedArgs, offline: bool) -> anyhow::Result<()> {
+    let config = load_config(&[&args.config]).await?;
+    let offline = is_offline(config.as_ref(), offline);
+    let Some(vscode_version) = get_vscode_version(config.as_ref(), args.vscode_version.as_deref())?
+    else {
+        return Err(Error::VscodeVersionMissing(args.config).into());
+    };
+
+    if args.changelog.is_some() && offline {
+        return Err(Error::Offline("--changelog".into()).into());
+    }
+
+    let content = fs::read_to_string(&args.file).await?;
+    let pinned = read_pinned(&content);
+    if pinned.is_empty() {
+        warn!("no extension found in {}", args.file);
+        return Ok(());
+    }
+
+    let client = http_client(config.as_ref(), offline)?;
+    let extensions = pinned
+        .iter()
+        .unique_by(|item| (item.publisher.to_lowercase(), item.name.to_lowercase()))
+        .map(|item| Extension {
+            publisher_name: item.publisher.clone(),
+            extension_name: item.name.clone(),
+            asset_url: None,
+            system: None,
+        })
+        .collect_vec();
+
+    let mut gallery = BTreeMap::new();
+    let mut iter = pin!(client.get_extension_response(extensions));
+    while let Some(item) = iter.next().await {
+        for item in item?.extensions {
+            gallery.insert(item.to_string().to_lowercase(), item);
+        }
+    }
+
+    let mut rows = vec![];
+    let mut bumps = vec![];
+    for item in &pinned {
+        let id = format!("{}.{}", item.publisher, item.name);
+        let Some(ext) = gallery.get(&id.to_lowercase()) else {
+            warn!("{id} is not in the marketplace");
+            continue;
+        };
+
+        let compatible = get_latest_compatible_version(ext, &vscode_version)
+            .map(|v| v.to_string())
+            .unwrap_or("-".into());
+        let (latest, engine) = match get_latest_version(ext) {
+            Some(v) => (v.version.clone(), v.get_engine().unwrap_or("-".into())),
+            None => ("-".into(), "-".into()),
+        };
+
+        if is_bump(&item.version, &compatible) {
+            bumps.push((id.clone(), item.version.clone(), ext, compatible.clone()));
+        }
+        if !args.all && compatible == item.version && latest == item.version {
+            continue;
+        }
+        rows.push([id, item.version.clone(), compatible, latest, engine]);
+    }
+
+    if rows.is_empty() {
+        println!("All extensions are up to date");
+        return Ok(());
+    }
+
+    print_table(["ID", "PINNED", "COMPATIBLE", "LATEST", "ENGINE"], &rows);
+    println!("\nCOMPATIBLE is the newest version for VS Code {vscode_version}");
+
+    if let Some(output) = &args.changelog {
+        let network = config.map(|config| config.network).unwrap_or_default();
+        let backend = ReqwestBackend::new(&network)?;
+        let mut report = vec![];
+        for (id, from, ext, to) in bumps {
+            report.push(changelog_report(&backend, &id, &from, ext, &to).await);
+        }
+        let report = report.join("\n\n") + "\n";
+        match output.as_str() {
+            "-" => print!("\n{report}"),
+            _ => fs::write(output, report).await?,
+        }
+    }
+
+    Ok(())
+}
+
+/// A markdown section on what changed in `ext` from version `from` to `to`,
+/// taken from the changelog published with `to`.
+async fn changelog_report(
+    backend: &ReqwestBackend,
+    id: &str,
+    from: &str,
+    ext: &IRawGalleryExtension,
+    to: &str,
+) -> String {
+    let title = format!("## {id}: {from} → {to}");
+    let Some(url) = ext
+        .versions
+        .iter()
+        .filter(|v| v.version == to)
+        .find_map(|v| v.get_file(AssetType::Changelog))
+        .map(|file| file.source.clone())
+    else {
+        return format!("{title}\n\nNo changelog is published.");
+    };
+
+    let content = match backend.download(&url).await {
+        Ok(content) => String::from_utf8_lossy(&content).into_owned(),
+        Err(err) => {
+            warn!("fail to download the changelog of {id}: {err}");
+            return format!("{title}\n\nFailed to download the [changelog]({url}).");
+        }
+    };
+    match changelog_between(&content, from, to, 3) {
+        Some(changes) => format!("{title}\n\n{changes}"),
+        None => format!("{title}\n\nNo entries between {from} and {to} in the [changelog]({url})."),
+    }
+}
+
+/// Whether moving from `pinned` to `compatible` is an upgrade, a pinned
+/// pre-release may be newer than the newest compatible stable version.
+fn is_bump(pinned: &str, compatible: &str) -> bool {
+    match (Version::from_str(pinned), Version::from_str(compatible)) {
+        (Ok(pinned), Ok(compatible)) => compatible > pinned,
+        _ => false,
+    }
+}
+
+/// The newest stable version, whatever engine it requires.
+fn get_latest_version(item: &IRawGalleryExtension) -> Option<&IRawGalleryExtensionVersion> {
+    item.versions
+        .iter()
+        .filter(|v| !v.is_pre_release_version())
+        .filter_map(|v| Version::from_str(&v.version).ok().map(|ver| (ver, v)))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, v)| v)
+}
+
+/// Pinned versions, read from the json written by `--export` or from the
+/// generated nix expression.
+fn read_pinned(content: &str) -> Vec<Pinned> {
+    if let Ok(items) = serde_json::from_str::<Vec<Pinned>>(content) {
+        return items.into_iter().sorted().dedup().collect();
+    }
+
+    let marketplace = regex!(
+        r#"name = "(?P<name>[^"]+)";\s*publisher = "(?P<publisher>[^"]+)";\s*version = "(?P<version>[^"]+)";"#
+    );
+    let custom = regex!(
+        r#"vscodeExtPublisher = "(?P<publisher>[^"]+)";\s*vscodeExtName = "(?P<name>[^"]+)";[\s\S]*?version = "(?P<version>[^"]+)";"#
+    );
+
+    marketplace
+        .captures_iter(content)
+        .chain(custom.captures_iter(content))
+        .map(|cap| Pinned {
+            publisher: cap["publisher"].into(),
+            name: cap["name"].into(),
+            version: cap["version"].into(),
+        })
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_pinned() {
+        let json = r#"[
+  {
+    "name": "debugpy",
+    "publisher": "ms-python",
+    "version": "2024.0.0",
+    "asset_url": null,
+    "sha256": "",
+    "platform": "linux-x64"
+  },
+  {
+    "name": "debugpy",
+    "publisher": "ms-python",
+    "version": "2024.0.0",
+    "asset_url": null,
+    "sha256": "",
+    "platform": "darwin-arm64"
+  }
+]"#;
+        assert_eq!(
+            read_pinned(json),
+            vec![Pinned {
+                publisher: "ms-python".into(),
+                name: "debugpy".into(),
+                version: "2024.0.0".into(),
+            }]
+        );
+
+        let nix = r#"
+  "ms-python"."debugpy" = vscode-utils.extensionFromVscodeMarketplace {
+    name = "debugpy";
+    publisher = "ms-python";
+    version = "2024.0.0";
+    sha256 = "1mzyc3sinkg4zmbyh2a85iqdqa7wsnh99hqvk6f8m2jcfhpfrwyb";
+  };
+  "vadimcn"."vscode-lldb" = (vscode-utils.buildVscodeExtension {
+    name = "vadimcn.vscode-lldb";
+    vscodeExtPublisher = "vadimcn";
+    vscodeExtName = "vscode-lldb";
+    src = (pkgs.fetchurl {
+      url = "https://example.com/codelldb.vsix";
+      sha256 = "1mzyc3sinkg4zmbyh2a85iqdqa7wsnh99hqvk6f8m2jcfhpfrwyb";
+      name = "vadimcn.vscode-lldb.zip";
+    }).outPath;
+    vscodeExtUniqueId = "vadimcn.vscode-lldb";
+    version = "1.10.0";
+  });
+"#;
+        assert_eq!(
+            read_pinned(nix),
+            vec![
+                Pinned {
+                    publisher: "ms-python".into(),
+                    name: "debugpy".into(),
+                    version: "2024.0.0".into(),
+                },
+                Pinned {
+                    publisher: "vadimcn".into(),
+                    name: "vscode-lldb".into(),
+                    version: "1.10.0".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_bump() {
+        assert!(is_bump("1.0.0", "1.1.0"));
+        assert!(!is_bump("1.1.0", "1.1.0"));
+        assert!(!is_bump("2024.1.1", "2024.0.0"));
+        assert!(!is_bump("1.0.0", "-"));
+    }
+
+    #[tokio::test]
+    async fn test_offline_config() {
+        let config =
+            std::env::temp_dir().join(format!("nix4vscode-outdated-{}.toml", std::process::id()));
+        std::fs::write(&config, "vscode_version = \"1.90.0\"\noffline = true\n").unwrap();
+        let args = OutdatedArgs {
+            file: "extensions.nix".into(),
+            config: config.display().to_string(),
+            vscode_version: None,
+            all: false,
+            changelog: Some("-".into()),
+        };
+        let err = run(args, false).await.unwrap_err();
+        std::fs::remove_file(config).unwrap();
+        assert!(
+            matches!(err.downcast_ref(), Some(Error::Offline(_))),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::str::FromStr;
+
+use lazy_regex::regex;
+use semver::Version;
+
+/// The sections of a markdown changelog for versions after `from` up to and
+/// including `to`, newest first as in the changelog. Their headings are
+/// shifted to `level`, so that they nest under a heading of the report.
+pub fn changelog_between(content: &str, from: &str, to: &str, level: usize) -> Option<String> {
+    let (from, to) = (parse_version(from)?, parse_version(to)?);
+
+    let mut fence = false;
+    let lines: Vec<(Option<usize>, &str)> = content
+        .lines()
+        .map(|line| {
+            if line.trim_start().starts_with("```") {
+                fence = !fence;
+            }
+            let heading = regex!(r"^(#{1,6})\s").captures(line);
+            match heading {
+                Some(cap) if !fence => (Some(cap[1].len()), line),
+                _ => (None, line),
+            }
+        })
+        .collect();
+
+    // the level of the first heading naming a version is the one of releases
+    let release = lines
+        .iter()
+        .find_map(|(heading, line)| heading.filter(|_| heading_version(line).is_some()))?;
+
+    let mut sections = vec![];
+    let mut current: Option<Vec<String>> = None;
+    for (heading, line) in lines {
+        if heading.is_some_and(|heading| heading <= release) {
+            sections.extend(current.take());
+            let included = heading == Some(release)
+                && heading_version(line).is_some_and(|version| from < version && version <= to);
+            if included {
+                current = Some(vec![]);
+            }
+        }
+        if let Some(section) = &mut current {
+            section.push(shift_heading(line, heading, release, level));
+        }
+    }
+    sections.extend(current);
+
+    let sections: Vec<String> = sections
+        .into_iter()
+        .map(|lines| lines.join("\n").trim().to_owned())
+        .collect();
+    (!sections.is_empty()).then(|| sections.join("\n\n"))
+}
+
+fn shift_heading(line: &str, heading: Option<usize>, release: usize, level: usize) -> String {
+    match heading {
+        Some(heading) => {
+            let shifted = (heading + level).saturating_sub(release).clamp(1, 6);
+            format!("{}{}", "#".repeat(shifted), &line[heading..])
+        }
+        None => line.to_owned(),
+    }
+}
+
+/// `## [1.2.0] - 2024-01-01`, `### v1.2` and the like.
+fn heading_version(line: &str) -> Option<Version> {
+    regex!(r"\bv?(\d+\.\d+(?:\.\d+)?(?:-[0-9A-Za-z.]+)?)")
+        .captures(line)
+        .and_then(|cap| parse_version(&cap[1]))
+}
+
+/// Versions are semver, but changelogs often leave out the patch.
+fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim().trim_start_matches('v');
+    Version::from_str(version)
+        .or_else(|_| Version::from_str(&format!("{version}.0")))
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_changelog_between() {
+        let content = r#"# Change Log
+
+## [1.3.0] - 2024-03-01
+
+### Added
+
+- foo
+
+```md
+# not a heading
+```
+
+## 1.2.1
+
+- bar
+
+## v1.2
+
+- baz
+
+## [1.1.0]
+
+- old
+"#;
+        let res = changelog_between(content, "1.1.0", "1.2.1", 3).unwrap();
+        assert_eq!(res, "### 1.2.1\n\n- bar\n\n### v1.2\n\n- baz");
+
+        let res = changelog_between(content, "1.2.1", "1.3.0", 3).unwrap();
+        assert!(res.starts_with("### [1.3.0] - 2024-03-01\n\n#### Added"));
+        assert!(res.contains("# not a heading"));
+        assert!(!res.contains("bar"));
+
+        assert_eq!(changelog_between(content, "1.3.0", "1.3.0", 3), None);
+        assert_eq!(
+            changelog_between("no versions here", "1.0.0", "2.0.0", 3),
+            None
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use clap::{Args, ValueEnum};
+use code_api::code::{IRawGalleryExtension, Query, SortBy};
+use itertools::Itertools;
+use tokio::fs;
+use tracing::*;
+
+use super::{http_client, is_offline, load_config, print_table, split_extension_id};
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SortKey {
+    Installs,
+    Rating,
+    Updated,
+    Name,
+}
+
+impl From<SortKey> for SortBy {
+    fn from(value: SortKey) -> Self {
+        match value {
+            SortKey::Installs => SortBy::INSTALL_COUNT,
+            SortKey::Rating => SortBy::WEIGHTED_RATING,
+            SortKey::Updated => SortBy::LAST_UPDATED_DATE,
+            SortKey::Name => SortBy::TITLE,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    text: String,
+    #[arg(long, value_enum)]
+    sort: Option<SortKey>,
+    #[arg(long)]
+    category: Vec<String>,
+    #[arg(long)]
+    tag: Vec<String>,
+    #[arg(long, default_value_t = 1)]
+    page: u64,
+    #[arg(long, default_value_t = 20)]
+    page_size: u64,
+    /// Append the extension in the given row, or the given `publisher.name`,
+    /// to the config file
+    #[arg(long, value_name = "ROW|ID")]
+    add: Option<String>,
+    #[arg(short, long, default_value = "config.toml")]
+    pub(super) config: String,
+}
+
+pub async fn run(args: SearchArgs, offline: bool) -> anyhow::Result<()> {
+    let config = load_config(&[&args.config]).await?;
+    let client = http_client(config.as_ref(), is_offline(config.as_ref(), offline))?;
+    let query = Query::create_gallery_search(
+        &args.text,
+        &args.category,
+        &args.tag,
+        args.sort
+            .map(Into::into)
+            .unwrap_or(SortBy::NONE_OR_RELEVANCE),
+        args.page,
+        args.page_size,
+    );
+
+    let extensions = client
+        .query(&query)
+        .await?
+        .results
+        .into_iter()
+        .next()
+        .filter(|result| !result.extensions.is_empty());
+    match &extensions {
+        Some(result) => {
+            print_extensions(&result.extensions);
+            if let Some(total) = result.get_total_count() {
+                println!(
+                    "\npage {}/{} ({total} extensions)",
+                    args.page,
+                    total.div_ceil(args.page_size.max(1))
+                );
+            }
+        }
+        None => println!("No extension found"),
+    }
+
+    if let Some(add) = &args.add {
+        let id = match add.parse::<usize>() {
+            Ok(row) => row
+                .checked_sub(1)
+                .and_then(|idx| extensions.as_ref()?.extensions.get(idx))
+                .ok_or(Error::InvalidRow(row))?
+                .to_string(),
+            Err(_) => add.clone(),
+        };
+        add_to_config(&args.config, &id).await?;
+    }
+
+    Ok(())
+}
+
+fn print_extensions(extensions: &[IRawGalleryExtension]) {
+    let rows = extensions
+        .iter()
+        .enumerate()
+        .map(|(idx, item)| {
+            [
+                (idx + 1).to_string(),
+                item.to_string(),
+                item.versions
+                    .first()
+                    .map(|v| v.version.clone())
+                    .unwrap_or_default(),
+                item.get_statistic(IRawGalleryExtension::STATISTIC_INSTALL)
+                    .map(|v| format!("{v:.0}"))
+                    .unwrap_or("-".into()),
+                item.get_statistic(IRawGalleryExtension::STATISTIC_AVERAGE_RATING)
+                    .map(|v| format!("{v:.1}"))
+                    .unwrap_or("-".into()),
+            ]
+        })
+        .collect_vec();
+
+    print_table(["#", "ID", "VERSION", "INSTALLS", "RATING"], &rows);
+}
+
+async fn add_to_config(path: &str, id: &str) -> anyhow::Result<()> {
+    let content = fs::read_to_string(path).await.unwrap_or_default();
+    let mut doc: toml_edit::DocumentMut = content.parse()?;
+
+    if !append_extension(&mut doc, id)? {
+        info!("{id} is already in {path}");
+        return Ok(());
+    }
+
+    fs::write(path, doc.to_string()).await?;
+    info!("add {id} to {path}");
+
+    Ok(())
+}
+
+/// Appends `id` to `extensions`, returns false if it is already there.
+fn append_extension(doc: &mut toml_edit::DocumentMut, id: &str) -> anyhow::Result<bool> {
+    let (publisher_name, extension_name) = split_extension_id(id)?;
+    let is_same = |publisher: Option<&str>, name: Option<&str>| {
+        publisher.is_some_and(|v| v.eq_ignore_ascii_case(publisher_name))
+            && name.is_some_and(|v| v.eq_ignore_ascii_case(extension_name))
+    };
+
+    let extensions = doc
+        .entry("extensions")
+        .or_insert(toml_edit::value(toml_edit::Array::new()));
+
+    if let Some(tables) = extensions.as_array_of_tables_mut() {
+        // [[extensions]]
+        if tables.iter().any(|item| {
+            is_same(
+                item.get("publisher_name").and_then(|v| v.as_str()),
+                item.get("extension_name").and_then(|v| v.as_str()),
+            )
+        }) {
+            return Ok(false);
+        }
+        let mut table = toml_edit::Table::new();
+        table.insert("publisher_name", toml_edit::value(publisher_name));
+        table.insert("extension_name", toml_edit::value(extension_name));
+        tables.push(table);
+        return Ok(true);
+    }
+
+    // extensions = [ ... ]
+    let extensions = extensions
+        .as_array_mut()
+        .ok_or(Error::AttributeMissing("extensions".into()))?;
+    if extensions.iter().any(|item| match item {
+        toml_edit::Value::String(v) => {
+            let (publisher, name) = v.value().split_once('.').unzip();
+            is_same(publisher, name)
+        }
+        toml_edit::Value::InlineTable(v) => is_same(
+            v.get("publisher_name").and_then(|v| v.as_str()),
+            v.get("extension_name").and_then(|v| v.as_str()),
+        ),
+        _ => false,
+    }) {
+        return Ok(false);
+    }
+
+    // keep the layout of a multi-line array
+    let decor = extensions.iter().last().map(|item| item.decor().clone());
+    extensions.push(id);
+    if let (Some(decor), Some(item)) = (decor, extensions.iter_mut().last()) {
+        *item.decor_mut() = decor;
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_append_extension() {
+        let mut doc: toml_edit::DocumentMut = r#"vscode_version = "1.84.2"
+
+extensions = [
+  "ms-python.python",
+  { publisher_name = "ms-python", extension_name = "debugpy" },
+]
+"#
+        .parse()
+        .unwrap();
+
+        assert!(!append_extension(&mut doc, "MS-Python.Python").unwrap());
+        assert!(!append_extension(&mut doc, "ms-python.debugpy").unwrap());
+        assert!(append_extension(&mut doc, "rust-lang.rust-analyzer").unwrap());
+        assert_eq!(
+            doc.to_string(),
+            r#"vscode_version = "1.84.2"
+
+extensions = [
+  "ms-python.python",
+  { publisher_name = "ms-python", extension_name = "debugpy" },
+  "rust-lang.rust-analyzer",
+]
+"#
+        );
+
+        let mut doc: toml_edit::DocumentMut = r#"vscode_version = "1.84.2"
+
+[[extensions]]
+publisher_name = "ms-python"
+extension_name = "debugpy"
+"#
+        .parse()
+        .unwrap();
+        assert!(append_extension(&mut doc, "rust-lang.rust-analyzer").unwrap());
+        let config = crate::config::Config::new(&doc.to_string()).unwrap();
+        assert!(config.contains("rust-lang", "rust-analyzer"));
+
+        for id in ["rust-analyzer", ".rust-analyzer", "rust-lang."] {
+            let err = append_extension(&mut doc, id).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::InvalidExtensionId(_))
+            ));
+        }
+    }
+}
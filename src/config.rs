@@ -1,11 +1,17 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
 use anyhow::anyhow;
-use code_api::config::{Extension, SystemContext};
+use code_api::config::{Extension, NetworkConfig, SystemContext};
+use futures::future::BoxFuture;
 use lazy_regex::regex;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
+use toml::{Table, Value};
 use tracing::error;
 
 use crate::jinja::Generator;
+use crate::utils::{CacheMode, HttpCache};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 #[serde(untagged)]
@@ -14,15 +20,108 @@ pub enum ExtensionType {
     V2(String),
 }
 
+/// How a problem found in the config is reported.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportLevel {
+    #[default]
+    Warning,
+    Error,
+}
+
+/// What the extensions are selected for.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    #[default]
+    Desktop,
+    /// Browser-hosted editors such as vscode.dev, only web extensions run there.
+    Web,
+}
+
+/// `[signature]`, checks VSIX files against the `VsixSignature` asset of
+/// the gallery.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SignatureConfig {
+    pub verify: bool,
+    /// PEM files of the certificates a signature has to chain up to.
+    pub roots: Vec<PathBuf>,
+    /// Unsigned extensions and invalid signatures.
+    pub unverified: ReportLevel,
+}
+
+/// `[policy]`, rules every resolved extension has to pass. Extensions are
+/// matched by publisher or by `publisher.name`, ignoring case.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// Only these are allowed, unless empty.
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    /// The publisher has to own a verified domain.
+    pub verified_publisher: bool,
+    pub min_installs: Option<u64>,
+    /// Average rating out of 5.
+    pub min_rating: Option<f64>,
+    /// Extensions using proposed VS Code APIs.
+    pub deny_api_proposals: bool,
+    /// Hosts a VSIX may be downloaded from, with their subdomains. Any if empty.
+    pub asset_domains: Vec<String>,
+}
+
+/// `[api_proposals]`, stable VS Code only enables proposed APIs for the
+/// extensions its product.json lists, any other extension relying on them
+/// fails to activate.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ApiProposalsConfig {
+    /// Extensions VS Code enables proposed APIs for, as `publisher.name`.
+    pub enabled: Vec<String>,
+    /// Pick the newest compatible version without proposed APIs instead.
+    pub prefer_without: bool,
+}
+
+/// `[profiles.<name>]`, a set of extensions rendered on its own.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ProfileConfig {
+    /// Profiles whose extensions this one has as well.
+    pub inherits: Vec<String>,
+    pub extensions: Vec<ExtensionType>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct Config {
     pub vscode_version: String,
     pub autogen_warning: Option<String>,
     pub extensions: Vec<ExtensionType>,
+    pub profiles: BTreeMap<String, ProfileConfig>,
     pub system: Option<SystemContext>,
+    pub target: Target,
+    /// Render the extensions of each side of a remote connection as `ui`
+    /// and `workspace` attrsets.
+    pub split_sides: bool,
+    /// Extensions in the config that match nothing in the marketplace.
+    pub unknown_extension: ReportLevel,
+    /// Seconds a cached gallery response stays valid, 0 disables the cache.
+    pub http_cache_ttl: u64,
+    pub cache_dir: Option<PathBuf>,
+    pub cache_mode: Option<CacheMode>,
+    /// Never touch the network, everything comes from the cache.
+    pub offline: bool,
+    /// Check that a downloaded VSIX is the extension and version the gallery
+    /// described, see [`VsixIdentity`](crate::utils::VsixIdentity).
+    pub verify_vsix: bool,
+    pub signature: SignatureConfig,
+    pub policy: PolicyConfig,
+    pub api_proposals: ApiProposalsConfig,
+    pub network: NetworkConfig,
     #[serde(skip)]
     pub(crate) handled_extensions: Vec<Extension>,
+    #[serde(skip)]
+    handled_profiles: BTreeMap<String, BTreeSet<String>>,
 }
 
 impl Default for Config {
@@ -34,46 +133,88 @@ impl Default for Config {
                     .into(),
             ),
             extensions: Default::default(),
+            profiles: Default::default(),
             system: None,
+            target: Default::default(),
+            split_sides: false,
+            unknown_extension: Default::default(),
+            http_cache_ttl: HttpCache::DEFAULT_TTL.as_secs(),
+            cache_dir: None,
+            cache_mode: None,
+            offline: false,
+            verify_vsix: true,
+            signature: Default::default(),
+            policy: Default::default(),
+            api_proposals: Default::default(),
+            network: Default::default(),
             handled_extensions: Default::default(),
+            handled_profiles: Default::default(),
         }
     }
 }
 
 impl Config {
     pub async fn from_file(path: &str) -> anyhow::Result<Self> {
-        let content = fs::read_to_string(path).await?;
-        Self::new(&content)
+        Self::from_files(&[path]).await
     }
 
+    /// Layers `paths` in order, each of them over the files it includes.
+    pub async fn from_files(paths: &[impl AsRef<Path>]) -> anyhow::Result<Self> {
+        let mut table = Table::new();
+        for path in paths {
+            merge_layer(&mut table, load_layer(path.as_ref(), &mut vec![]).await?);
+        }
+        Self::from_table(table)
+    }
+
+    /// `include` needs a file to be relative to, see [`Config::from_file`].
     pub fn new(content: &str) -> anyhow::Result<Self> {
-        let mut obj: Config = toml::from_str(content)?;
-        let reg = regex!(r#"(\d+.\d+.\d+)(.*)?"#)
-            .captures(&obj.vscode_version)
-            .ok_or(anyhow!(format!("bad code version: {}", obj.vscode_version)))
-            .unwrap();
-        assert_eq!(3, reg.len());
+        let table: Table = toml::from_str(content)?;
+        if table.contains_key(INCLUDE) {
+            return Err(anyhow!("{INCLUDE} is only supported in config files"));
+        }
+        Self::from_table(table)
+    }
 
-        obj.vscode_version = reg[1].to_string();
+    fn from_table(table: Table) -> anyhow::Result<Self> {
+        let mut obj: Config = table.try_into()?;
+        obj.vscode_version = normalize_vscode_version(&obj.vscode_version)?;
         let extensions = std::mem::take(&mut obj.extensions);
-        for item in extensions {
-            match item {
-                ExtensionType::V1(v) => obj.handled_extensions.push(v),
-                ExtensionType::V2(v) => {
-                    let item = v.split_once(".");
-                    match item {
-                        Some((publisher, extension)) => obj.handled_extensions.push(Extension {
-                            publisher_name: publisher.to_string(),
-                            extension_name: extension.to_string(),
-                            asset_url: None,
-                            system: None,
-                        }),
-                        None => {
-                            error!("config error: {v} is not a valid format");
-                        }
-                    };
+        obj.handled_extensions = extensions.into_iter().filter_map(parse_extension).collect();
+
+        let mut profiles = BTreeMap::new();
+        for name in obj.profiles.keys() {
+            // `--output-dir` writes every profile to `<name>.nix`
+            if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+                return Err(anyhow!("profile name {name:?} is not a valid file name"));
+            }
+            let items: Vec<Extension> = obj
+                .profile_chain(name)?
+                .into_iter()
+                .flat_map(|profile| profile.extensions.iter().cloned())
+                .filter_map(parse_extension)
+                .collect();
+            profiles.insert(name.clone(), items);
+        }
+        // the top level extensions are shared by every profile
+        let shared: BTreeSet<String> = obj
+            .handled_extensions
+            .iter()
+            .map(|item| item.id())
+            .collect();
+        for (name, items) in profiles {
+            let mut ids = shared.clone();
+            for item in items {
+                ids.insert(item.id());
+                if !obj
+                    .handled_extensions
+                    .iter()
+                    .any(|ext| ext.id() == item.id())
+                {
+                    obj.handled_extensions.push(item);
                 }
             }
+            obj.handled_profiles.insert(name, ids);
         }
 
         obj.handled_extensions.iter_mut().for_each(|item| {
@@ -93,6 +234,44 @@ impl Config {
         Ok(obj)
     }
 
+    /// `name` and every profile it inherits from, each once.
+    fn profile_chain(&self, name: &str) -> anyhow::Result<Vec<&ProfileConfig>> {
+        let mut res = vec![];
+        let mut visited = vec![];
+        let mut pending = vec![(name.to_owned(), vec![])];
+        while let Some((name, path)) = pending.pop() {
+            if path.contains(&name) {
+                return Err(anyhow!(
+                    "profile {name} inherits from itself: {} -> {name}",
+                    path.join(" -> ")
+                ));
+            }
+            if visited.contains(&name) {
+                continue;
+            }
+            let Some(profile) = self.profiles.get(&name) else {
+                return Err(anyhow!(
+                    "unknown profile {name} in inherits of {}",
+                    path.last().map_or("", String::as_str)
+                ));
+            };
+            let mut path = path;
+            path.push(name.clone());
+            for parent in &profile.inherits {
+                pending.push((parent.clone(), path.clone()));
+            }
+            visited.push(name);
+            res.push(profile);
+        }
+        Ok(res)
+    }
+
+    /// Lowercase ids of the extensions of every profile, with those it
+    /// inherits and the top level ones.
+    pub fn profiles(&self) -> &BTreeMap<String, BTreeSet<String>> {
+        &self.handled_profiles
+    }
+
     #[inline]
     fn get_idx(&self, publisher_name: &str, extension_name: &str) -> Option<usize> {
         self.handled_extensions.iter().position(|item| {
@@ -124,6 +303,155 @@ impl Config {
     }
 }
 
+/// Key of the files a config file is layered over, relative to it.
+const INCLUDE: &str = "include";
+
+/// The table of the config file at `path` layered over its includes.
+/// `stack` holds the files including it.
+fn load_layer<'a>(
+    path: &'a Path,
+    stack: &'a mut Vec<PathBuf>,
+) -> BoxFuture<'a, anyhow::Result<Table>> {
+    Box::pin(async move {
+        let path = fs::canonicalize(path)
+            .await
+            .map_err(|err| anyhow!("read {} failed: {err}", path.display()))?;
+        if stack.contains(&path) {
+            return Err(anyhow!("{} includes itself", path.display()));
+        }
+        let mut table: Table = toml::from_str(&fs::read_to_string(&path).await?)
+            .map_err(|err| anyhow!("{}: {err}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        // paths of the files given on the command line stay relative to the
+        // working directory
+        if !stack.is_empty() {
+            resolve_paths(&mut table, dir);
+        }
+        let includes: Vec<PathBuf> = match table.remove(INCLUDE) {
+            Some(value) => value
+                .try_into()
+                .map_err(|err| anyhow!("{INCLUDE} of {}: {err}", path.display()))?,
+            None => vec![],
+        };
+
+        let mut res = Table::new();
+        stack.push(path.clone());
+        for include in includes {
+            merge_layer(&mut res, load_layer(&dir.join(include), stack).await?);
+        }
+        stack.pop();
+        merge_layer(&mut res, table);
+        Ok(res)
+    })
+}
+
+/// Makes the relative paths of an included `table` relative to `dir`, the
+/// directory of its file.
+fn resolve_paths(table: &mut Table, dir: &Path) {
+    let resolve = |value: &mut Value| {
+        if let Value::String(path) = value {
+            if Path::new(path).is_relative() {
+                *path = dir.join(&path).to_string_lossy().into_owned();
+            }
+        }
+    };
+    let resolve_all = |value: Option<&mut Value>| {
+        if let Some(Value::Array(paths)) = value {
+            paths.iter_mut().for_each(resolve);
+        }
+    };
+
+    table.get_mut("cache_dir").map(resolve);
+    if let Some(Value::Table(signature)) = table.get_mut("signature") {
+        resolve_all(signature.get_mut("roots"));
+    }
+    if let Some(Value::Table(network)) = table.get_mut("network") {
+        resolve_all(network.get_mut("ca_certificates"));
+        network.get_mut("netrc_file").map(resolve);
+        if let Some(Value::Array(credentials)) = network.get_mut("credentials") {
+            for credential in credentials.iter_mut().filter_map(Value::as_table_mut) {
+                credential.get_mut("token_file").map(resolve);
+            }
+        }
+    }
+}
+
+/// Puts `layer` over `base`. Tables are merged key by key and `extensions`
+/// by id, anything else of `layer` replaces what `base` has.
+fn merge_layer(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge_layer(base, layer),
+            (Some(Value::Array(base)), Value::Array(layer)) if key == "extensions" => {
+                merge_extensions(base, layer)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// An extension of `layer` which `base` has already merges its fields into
+/// the one of `base`, a `"publisher.name"` adds nothing to it.
+fn merge_extensions(base: &mut Vec<Value>, layer: Vec<Value>) {
+    fn id(value: &Value) -> Option<String> {
+        match value {
+            Value::String(id) => Some(id.to_lowercase()),
+            Value::Table(table) => Some(
+                format!(
+                    "{}.{}",
+                    table.get("publisher_name")?.as_str()?,
+                    table.get("extension_name")?.as_str()?
+                )
+                .to_lowercase(),
+            ),
+            _ => None,
+        }
+    }
+
+    for value in layer {
+        let existing = id(&value).and_then(|expected| {
+            base.iter_mut()
+                .find(|item| id(item).as_deref() == Some(expected.as_str()))
+        });
+        match (existing, value) {
+            (Some(Value::Table(existing)), Value::Table(value)) => merge_layer(existing, value),
+            (Some(existing), value @ Value::Table(_)) => *existing = value,
+            (Some(_), _) => {}
+            (None, value) => base.push(value),
+        }
+    }
+}
+
+fn parse_extension(item: ExtensionType) -> Option<Extension> {
+    match item {
+        ExtensionType::V1(v) => Some(v),
+        ExtensionType::V2(v) => match v.split_once(".") {
+            Some((publisher, extension)) => Some(Extension {
+                publisher_name: publisher.to_string(),
+                extension_name: extension.to_string(),
+                asset_url: None,
+                system: None,
+            }),
+            None => {
+                error!("config error: {v} is not a valid format");
+                None
+            }
+        },
+    }
+}
+
+/// Strips build numbers and suffixes, `1.86.2.24057-preview` becomes `1.86.2`.
+pub fn normalize_vscode_version(version: &str) -> anyhow::Result<String> {
+    let reg = regex!(r#"(\d+.\d+.\d+)(.*)?"#)
+        .captures(version)
+        .ok_or(anyhow!(format!("bad code version: {}", version)))?;
+    assert_eq!(3, reg.len());
+
+    Ok(reg[1].to_string())
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
@@ -172,4 +500,152 @@ extensions = [
             Config::new(c).unwrap();
         }
     }
+
+    #[test]
+    fn test_profiles() {
+        let c = Config::new(
+            r#"
+vscode_version = "1.84.2"
+extensions = ["editorconfig.editorconfig"]
+
+[profiles.base]
+extensions = ["eamodio.gitlens"]
+
+[profiles.python]
+inherits = ["base"]
+extensions = ["ms-python.python", "EAMODIO.gitlens"]
+
+[profiles.rust]
+inherits = ["base"]
+extensions = ["rust-lang.rust-analyzer"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(c.handled_extensions.len(), 4);
+        assert_eq!(
+            c.profiles()["python"].iter().collect::<Vec<_>>(),
+            [
+                "eamodio.gitlens",
+                "editorconfig.editorconfig",
+                "ms-python.python"
+            ]
+        );
+        assert!(!c.profiles()["rust"].contains("ms-python.python"));
+
+        let cycle = r#"
+vscode_version = "1.84.2"
+[profiles.a]
+inherits = ["b"]
+[profiles.b]
+inherits = ["a"]
+        "#;
+        assert!(Config::new(cycle).is_err());
+        let unknown = r#"
+vscode_version = "1.84.2"
+[profiles.a]
+inherits = ["b"]
+        "#;
+        assert!(Config::new(unknown).is_err());
+        let profile = |name: &str| {
+            Config::new(&format!(
+                "vscode_version = \"1.84.2\"\n[profiles.\"{name}\"]"
+            ))
+        };
+        assert!(profile("python-3.12").is_ok());
+        for name in ["../escape", "a/b", "a\\\\b", ".."] {
+            assert!(profile(name).is_err(), "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_include() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/include");
+        let c = Config::from_files(&[format!("{dir}/repo.toml"), format!("{dir}/local.toml")])
+            .await
+            .unwrap();
+        assert_eq!(c.vscode_version, "1.90.0");
+        assert_eq!(c.unknown_extension, ReportLevel::Error);
+        assert_eq!(
+            c.handled_extensions
+                .iter()
+                .map(|item| item.id())
+                .collect::<Vec<_>>(),
+            [
+                "ms-python.python",
+                "foo.bar",
+                "rust-lang.rust-analyzer",
+                "eamodio.gitlens"
+            ]
+        );
+        assert_eq!(
+            c.get_asset_url("foo", "bar").as_deref(),
+            Some("https://example.com/b.vsix")
+        );
+        assert_eq!(c.policy.allow, ["rust-lang"]);
+        assert_eq!(c.policy.min_installs, Some(1000));
+
+        let err = Config::from_file(&format!("{dir}/cycle.toml"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("includes itself"));
+        assert!(Config::new(r#"include = ["base.toml"]"#).is_err());
+
+        let c = Config::from_file(&format!("{dir}/paths.toml"))
+            .await
+            .unwrap();
+        let shared = Path::new(dir).canonicalize().unwrap().join("shared");
+        assert_eq!(c.cache_dir, Some(shared.join("cache")));
+        assert_eq!(
+            c.signature.roots,
+            [shared.join("root.pem"), PathBuf::from("/etc/corp/root.pem")]
+        );
+        assert_eq!(c.network.ca_certificates, [shared.join("ca.pem")]);
+        assert_eq!(c.network.netrc_file, Some(shared.join("netrc")));
+        assert_eq!(
+            c.network.credentials[0].token_file,
+            Some(shared.join("token"))
+        );
+    }
+
+    #[test]
+    fn test_unknown_extension() {
+        let c = Config::new(r#"vscode_version = "1.84.2""#).unwrap();
+        assert_eq!(c.unknown_extension, ReportLevel::Warning);
+
+        let c = Config::new(
+            r#"
+vscode_version = "1.84.2"
+unknown_extension = "error"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(c.unknown_extension, ReportLevel::Error);
+    }
+
+    #[test]
+    fn test_network() {
+        let c = Config::new(r#"vscode_version = "1.84.2""#).unwrap();
+        assert_eq!(c.network, NetworkConfig::default());
+
+        let c = Config::new(
+            r#"
+vscode_version = "1.84.2"
+
+[network]
+proxy = "http://proxy.corp:3128"
+no_proxy = "localhost,.corp"
+ca_certificates = ["/etc/corp/root.pem"]
+connect_timeout = 10
+            "#,
+        )
+        .unwrap();
+        assert_eq!(c.network.proxy.as_deref(), Some("http://proxy.corp:3128"));
+        assert_eq!(c.network.no_proxy.as_deref(), Some("localhost,.corp"));
+        assert_eq!(
+            c.network.ca_certificates,
+            vec![PathBuf::from("/etc/corp/root.pem")]
+        );
+        assert_eq!(c.network.connect_timeout, Some(10));
+        assert_eq!(c.network.read_timeout, None);
+    }
 }
@@ -8,6 +8,30 @@ pub enum Error {
     Sha256Error(String),
     #[error("CacheMissing {0}")]
     CacheMissing(String),
+    #[error("Cache is read-only")]
+    CacheReadOnly,
+    #[error("Cache {0} is used by another process")]
+    CacheLocked(String),
     #[error("Attribute {0} missing")]
     AttributeMissing(String),
+    #[error("Unknown extensions: {0}")]
+    UnknownExtensions(String),
+    #[error("Fail to mirror {0}")]
+    MirrorFailed(String),
+    #[error("VSIX does not match the gallery: {0}")]
+    VsixMismatch(String),
+    #[error("No valid signature: {0}")]
+    Unverified(String),
+    #[error("Policy violations: {0}")]
+    PolicyViolation(String),
+    #[error("Offline, run again with network access for: {0}")]
+    Offline(String),
+    #[error("Row {0} is not in the search results")]
+    InvalidRow(usize),
+    #[error("Invalid extension id {0}, expected publisher.name")]
+    InvalidExtensionId(String),
+    #[error(
+        "No VS Code version to compare against, pass --vscode-version or set vscode_version in {0}"
+    )]
+    VscodeVersionMissing(String),
 }
@@ -48,7 +48,10 @@ impl Generator<'_> {
 
         add_filter!(engine, nixfmt);
         add_filter!(engine, to_string);
+        add_filter!(engine, nix_string);
+        add_filter!(engine, on_side);
         add_function!(engine, is_universal);
+        add_function!(engine, is_web);
         add_function!(engine, is_linux_x86);
         add_function!(engine, is_linux_arm);
         add_function!(engine, is_darwin_x86);
@@ -75,3 +78,30 @@ impl Default for Generator<'_> {
         Self::new()
     }
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::config::Config;
+
+    /// `foo.<name>` with only the fields every extension has.
+    pub fn item(name: &str) -> NixContext {
+        NixContext {
+            name: name.into(),
+            publisher: "foo".into(),
+            version: "1.0.0".into(),
+            sha256: "abc".into(),
+            ..Default::default()
+        }
+    }
+
+    /// With whitespace collapsed, so assertions don't depend on indentation.
+    pub fn render(config: Config, extensions: Vec<NixContext>) -> String {
+        Generator::new()
+            .render(&GeneratorContext::new(config, extensions))
+            .unwrap()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
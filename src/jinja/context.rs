@@ -1,21 +1,66 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use code_api::code::TargetPlatform;
+use code_api::config::{AuthScheme, Credential, url_host};
 use serde::{Deserialize, Serialize};
+use tracing::*;
 
 mod asset_url;
+mod license;
+mod meta;
+mod native;
 
 pub use asset_url::*;
+pub use license::*;
+pub use meta::*;
+pub use native::*;
 
 use crate::config::Config;
+use crate::utils::{ExtensionKind, SignatureStatus};
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct GeneratorContext {
     pub config: Arc<Config>,
     pub extensions: Vec<NixContext>,
+    /// The extensions of each `[profiles.<name>]`, rendered as an attrset of
+    /// their own.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Vec<NixContext>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl GeneratorContext {
+    pub fn new(config: Config, extensions: Vec<NixContext>) -> Self {
+        let profiles = config
+            .profiles()
+            .iter()
+            .map(|(name, ids)| {
+                let extensions = extensions
+                    .iter()
+                    .filter(|item| ids.contains(&format!("{}.{}", item.publisher, item.name)))
+                    .cloned()
+                    .collect();
+                (name.clone(), extensions)
+            })
+            .collect();
+        Self {
+            config: config.into(),
+            extensions,
+            profiles,
+        }
+    }
+
+    /// The context of a single profile, as if its extensions were the only ones.
+    pub fn profile(&self, name: &str) -> Option<Self> {
+        Some(Self {
+            config: self.config.clone(),
+            extensions: self.profiles.get(name)?.clone(),
+            profiles: Default::default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NixContext {
     pub name: String,
     pub publisher: String,
@@ -23,4 +68,92 @@ pub struct NixContext {
     pub asset_url: Option<String>,
     pub sha256: String,
     pub platform: TargetPlatform,
+    /// The file `sha256` belongs to.
+    #[serde(default)]
+    pub source_url: String,
+    /// Fetch a marketplace extension from here instead of the marketplace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vsix_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub netrc: Option<NetrcContext>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native: Option<NativeContext>,
+    #[serde(default)]
+    pub meta: MetaContext,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<LicenseContext>,
+    /// `extensionKind` of the manifest, empty if unknown.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extension_kind: Vec<ExtensionKind>,
+    /// Only if signatures are verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureStatus>,
+}
+
+/// Lets the generated `fetchurl` write a netrc for a private asset, the
+/// password is taken from `password_env` in the environment of the builder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetrcContext {
+    pub machine: String,
+    pub login: String,
+    pub password_env: String,
+}
+
+impl NetrcContext {
+    /// Only basic credentials read from an environment variable can be passed on.
+    pub fn new(credential: &Credential, url: &str) -> Option<Self> {
+        if !credential.netrc_phase {
+            return None;
+        }
+        let (AuthScheme::Basic, Some(password_env)) = (credential.scheme, &credential.token_env)
+        else {
+            warn!(
+                "credential of {} needs scheme = \"basic\" and token_env for netrc_phase",
+                credential.host
+            );
+            return None;
+        };
+
+        Some(Self {
+            machine: url_host(url)?,
+            login: credential
+                .username
+                .clone()
+                .filter(|item| !item.is_empty())
+                .unwrap_or("nix4vscode".into()),
+            password_env: password_env.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jinja::test::{item, render};
+
+    #[test]
+    fn test_render_sides() {
+        let kind = |name: &str, extension_kind: Vec<ExtensionKind>| NixContext {
+            extension_kind,
+            ..item(name)
+        };
+        let mut config = Config::default();
+        config.split_sides = true;
+        let res = render(
+            config,
+            vec![
+                kind("theme", vec![ExtensionKind::Ui, ExtensionKind::Web]),
+                kind("lsp", vec![ExtensionKind::Workspace]),
+                kind("unknown", vec![]),
+            ],
+        );
+
+        let (ui, workspace) = res.split_once("workspace =").unwrap();
+        assert!(ui.contains("ui ="));
+        assert!(ui.contains(r#""foo"."theme" ="#) && !ui.contains(r#""foo"."lsp" ="#));
+        assert!(
+            workspace.contains(r#""foo"."lsp" ="#) && !workspace.contains(r#""foo"."theme" ="#)
+        );
+        assert!(ui.contains(r#""foo"."unknown" ="#) && workspace.contains(r#""foo"."unknown" ="#));
+    }
 }
@@ -0,0 +1,99 @@
+use lazy_regex::regex;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+/// `meta.license` of an extension, either SPDX ids for
+/// `lib.getLicenseFromSpdxId` or `lib.licenses.unfree`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LicenseContext {
+    pub spdx_ids: Vec<String>,
+    pub unfree: bool,
+}
+
+impl LicenseContext {
+    /// Classifies the `license` of a `package.json`. A license nix4vscode
+    /// cannot make sense of is unfree, so that `allowUnfree` still applies.
+    pub fn new(license: &str) -> Self {
+        let license = license.trim();
+        let proprietary = ["SEE LICENSE IN", "UNLICENSED", "PROPRIETARY"]
+            .iter()
+            .any(|prefix| license.to_uppercase().starts_with(prefix));
+
+        let spdx_ids: Vec<String> = regex!(r"\s+(?:OR|AND)\s+")
+            .split(license)
+            .map(|term| {
+                // `GPL-2.0 WITH Classpath-exception-2.0` is still GPL-2.0
+                let term = term.split(" WITH ").next().unwrap_or_default();
+                term.trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace())
+                    .to_owned()
+            })
+            .collect();
+        // `Commercial` or `BSD` look like ids, but are not on the SPDX list
+        let valid = spdx_ids.iter().all(|id| spdx::license_id(id).is_some());
+
+        if proprietary || !valid {
+            debug!("treat license {license:?} as unfree");
+            return Self {
+                spdx_ids: vec![],
+                unfree: true,
+            };
+        }
+        Self {
+            spdx_ids,
+            unfree: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jinja::NixContext;
+    use crate::jinja::test::{item, render};
+
+    #[test]
+    fn test_license_context() {
+        let spdx = |license: &str| LicenseContext::new(license).spdx_ids;
+        assert_eq!(spdx("MIT"), ["MIT"]);
+        assert_eq!(spdx("(MIT OR Apache-2.0)"), ["MIT", "Apache-2.0"]);
+        assert_eq!(
+            spdx("GPL-2.0-only WITH Classpath-exception-2.0"),
+            ["GPL-2.0-only"]
+        );
+        assert_eq!(spdx("BSD-3-Clause"), ["BSD-3-Clause"]);
+
+        for license in [
+            "SEE LICENSE IN LICENSE.txt",
+            "UNLICENSED",
+            "Proprietary",
+            "Microsoft Software License",
+            "Commercial",
+            "Custom",
+            "Microsoft",
+            "BSD",
+            "MIT OR Commercial",
+            "LicenseRef-Proprietary",
+        ] {
+            assert!(LicenseContext::new(license).unfree, "{license}");
+        }
+    }
+
+    #[test]
+    fn test_render_license() {
+        let licensed = |name: &str, license: &str| NixContext {
+            license: Some(LicenseContext::new(license)),
+            ..item(name)
+        };
+        let res = render(
+            Default::default(),
+            vec![
+                licensed("mit", "MIT"),
+                licensed("dual", "MIT OR Apache-2.0"),
+                licensed("pylance", "SEE LICENSE IN LICENSE.txt"),
+            ],
+        );
+        assert!(res.contains(r#"license = lib.getLicenseFromSpdxId "MIT";"#));
+        assert!(res.contains(r#"(lib.getLicenseFromSpdxId "Apache-2.0")"#));
+        assert!(res.contains("license = lib.licenses.unfree;"));
+    }
+}
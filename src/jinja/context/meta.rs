@@ -0,0 +1,111 @@
+use code_api::code::{AssetType, IRawGalleryExtension, IRawGalleryExtensionVersion, PropertyType};
+use serde::{Deserialize, Serialize};
+
+/// Gallery metadata of an extension, for `meta` and `passthru`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetaContext {
+    pub display_name: String,
+    pub publisher_display_name: String,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub repository: Option<String>,
+    /// The changelog asset of this version.
+    pub changelog: Option<String>,
+    pub categories: Vec<String>,
+    pub tags: Vec<String>,
+    /// Of this version.
+    pub last_updated: String,
+}
+
+impl MetaContext {
+    pub fn new(item: &IRawGalleryExtension, version: &IRawGalleryExtensionVersion) -> Self {
+        let repository = version
+            .get_property(PropertyType::SOURCE_LINK)
+            .map(str::to_owned);
+        Self {
+            display_name: item.display_name.clone(),
+            publisher_display_name: item.publisher.display_name.clone(),
+            description: item
+                .short_description
+                .clone()
+                .filter(|item| !item.trim().is_empty()),
+            homepage: version
+                .get_property(PropertyType::LEARN_LINK)
+                .map(str::to_owned)
+                .or(repository.clone()),
+            repository,
+            changelog: version
+                .get_file(AssetType::Changelog)
+                .map(|file| file.source.clone()),
+            categories: item.categories.clone().unwrap_or_default(),
+            // the gallery adds internal tags like `__web_extension`
+            tags: item
+                .tags
+                .iter()
+                .flatten()
+                .filter(|tag| !tag.starts_with("__"))
+                .cloned()
+                .collect(),
+            last_updated: version.last_updated.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use code_api::code::IRawGalleryExtensionProperty;
+
+    use super::*;
+    use crate::jinja::NixContext;
+    use crate::jinja::test::{item, render};
+    use crate::utils::ExtensionKind;
+
+    #[test]
+    fn test_meta_context() {
+        let property = |key: &str, value: &str| IRawGalleryExtensionProperty {
+            key: key.into(),
+            value: value.into(),
+        };
+        let mut version = IRawGalleryExtensionVersion {
+            properties: vec![property(
+                PropertyType::SOURCE_LINK,
+                "https://github.com/bar/foo",
+            )],
+            ..Default::default()
+        };
+        let meta = MetaContext::new(&Default::default(), &version);
+        assert_eq!(
+            meta.repository.as_deref(),
+            Some("https://github.com/bar/foo")
+        );
+        assert_eq!(meta.homepage, meta.repository);
+
+        version
+            .properties
+            .push(property(PropertyType::LEARN_LINK, "https://foo.dev"));
+        let meta = MetaContext::new(&Default::default(), &version);
+        assert_eq!(meta.homepage.as_deref(), Some("https://foo.dev"));
+    }
+
+    #[test]
+    fn test_render_meta() {
+        let res = render(
+            Default::default(),
+            vec![NixContext {
+                meta: MetaContext {
+                    description: Some(r#"Say "hi" to ${name}"#.into()),
+                    homepage: Some("https://github.com/bar/foo".into()),
+                    categories: vec!["Formatters".into()],
+                    ..Default::default()
+                },
+                extension_kind: vec![ExtensionKind::Workspace, ExtensionKind::Web],
+                ..item("foo")
+            }],
+        );
+        assert!(res.contains(r#"description = "Say \"hi\" to \${name}";"#));
+        assert!(res.contains(r#"homepage = "https://github.com/bar/foo";"#));
+        assert!(res.contains(r#"categories = [ "Formatters" ];"#));
+        assert!(res.contains(r#"extensionKind = [ "workspace" "web" ];"#));
+    }
+}
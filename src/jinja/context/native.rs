@@ -0,0 +1,153 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{BinaryFormat, NativeFile};
+
+/// Sonames by prefix and the nixpkgs attribute providing them.
+const LIBRARIES: &[(&str, &str)] = &[
+    ("libstdc++.so", "stdenv.cc.cc.lib"),
+    ("libgcc_s.so", "stdenv.cc.cc.lib"),
+    ("libatomic.so", "stdenv.cc.cc.lib"),
+    ("libz.so", "zlib"),
+    ("libssl.so", "openssl"),
+    ("libcrypto.so", "openssl"),
+    ("libicu", "icu"),
+    ("libkrb5.so", "krb5"),
+    ("libgssapi_krb5.so", "krb5"),
+    ("liblttng-ust.so", "lttng-ust"),
+    ("libsecret-1.so", "libsecret"),
+    ("libglib-2.0.so", "glib"),
+    ("libgobject-2.0.so", "glib"),
+    ("libgio-2.0.so", "glib"),
+    ("libcurl.so", "curl"),
+    ("libunwind.so", "libunwind"),
+    ("libuuid.so", "libuuid"),
+    ("libsqlite3.so", "sqlite"),
+    ("libX11.so", "xorg.libX11"),
+    ("libxkbfile.so", "xorg.libxkbfile"),
+];
+
+/// Part of glibc, which every stdenv build already links against.
+const GLIBC: &[&str] = &[
+    "libc.so",
+    "libm.so",
+    "libdl.so",
+    "librt.so",
+    "libpthread.so",
+    "libutil.so",
+    "libresolv.so",
+    "ld-linux",
+];
+
+/// Native binaries of an extension, rendered as `autoPatchelfHook` with its
+/// inputs and a `chmod +x`, since a VSIX does not keep file modes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NativeContext {
+    pub executables: Vec<String>,
+    /// Whether there is anything for `autoPatchelfHook` to do.
+    pub elf: bool,
+    /// nixpkgs attributes providing the libraries the ELF files link against.
+    pub build_inputs: Vec<String>,
+    /// Libraries neither shipped in the VSIX nor known to come from nixpkgs.
+    pub missing: Vec<String>,
+}
+
+impl NativeContext {
+    pub fn new(files: &[NativeFile]) -> Option<Self> {
+        if files.is_empty() {
+            return None;
+        }
+
+        let shipped: BTreeSet<&str> = files
+            .iter()
+            .filter_map(|file| file.path.rsplit('/').next())
+            .collect();
+        let mut build_inputs = BTreeSet::new();
+        let mut missing = BTreeSet::new();
+        for lib in files
+            .iter()
+            .filter(|file| file.format == BinaryFormat::Elf)
+            .flat_map(|file| &file.libraries)
+        {
+            if shipped.contains(lib.as_str()) || GLIBC.iter().any(|item| lib.starts_with(item)) {
+                continue;
+            }
+            match LIBRARIES.iter().find(|(prefix, _)| lib.starts_with(prefix)) {
+                Some((_, attr)) => build_inputs.insert(attr.to_string()),
+                None => missing.insert(lib.clone()),
+            };
+        }
+
+        Some(Self {
+            executables: files.iter().map(|file| file.path.clone()).collect(),
+            elf: files.iter().any(|file| file.format == BinaryFormat::Elf),
+            build_inputs: build_inputs.into_iter().collect(),
+            missing: missing.into_iter().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use code_api::code::TargetPlatform;
+
+    use super::*;
+    use crate::jinja::NixContext;
+    use crate::jinja::test::{item, render};
+
+    #[test]
+    fn test_native_context() {
+        let elf = |path: &str, libraries: &[&str]| NativeFile {
+            path: path.into(),
+            format: BinaryFormat::Elf,
+            interpreter: Some("/lib64/ld-linux-x86-64.so.2".into()),
+            libraries: libraries.iter().map(|lib| lib.to_string()).collect(),
+        };
+        let ctx = NativeContext::new(&[
+            elf(
+                "bin/cpptools",
+                &["libstdc++.so.6", "libc.so.6", "libfoo.so.1", "libz.so.1"],
+            ),
+            elf(
+                "bin/libfoo.so.1",
+                &["libgcc_s.so.1", "liblttng-ust.so.0", "libbar.so.2"],
+            ),
+            NativeFile {
+                path: "bin/cpptools-mac".into(),
+                format: BinaryFormat::MachO,
+                interpreter: None,
+                libraries: vec!["/usr/lib/libSystem.B.dylib".into()],
+            },
+        ])
+        .unwrap();
+
+        assert!(ctx.elf);
+        assert_eq!(ctx.executables.len(), 3);
+        assert_eq!(ctx.build_inputs, ["lttng-ust", "stdenv.cc.cc.lib", "zlib"]);
+        assert_eq!(ctx.missing, ["libbar.so.2"]);
+        assert!(NativeContext::new(&[]).is_none());
+    }
+
+    #[test]
+    fn test_render_native() {
+        let res = render(
+            Default::default(),
+            vec![NixContext {
+                platform: TargetPlatform::LinuxX64,
+                native: Some(NativeContext {
+                    executables: vec!["bin/server".into(), "bin/libfoo.so".into()],
+                    elf: true,
+                    build_inputs: vec!["zlib".into()],
+                    missing: vec!["libbar.so.2".into()],
+                }),
+                ..item("server")
+            }],
+        );
+        assert!(
+            res.contains("nativeBuildInputs = lib.optionals isLinux [ pkgs.autoPatchelfHook ];")
+        );
+        assert!(res.contains(r#"autoPatchelfIgnoreMissingDeps = [ "libbar.so.2" ];"#));
+        assert!(res.contains(r#"chmod +x "bin/server" "bin/libfoo.so""#));
+    }
+}
@@ -1,6 +1,9 @@
 use code_api::code::TargetPlatform;
 use minijinja::Value;
 use nixpkgs_fmt::reformat_string;
+use serde::Deserialize;
+
+use crate::utils::{ExtensionKind, ExtensionSide};
 
 pub fn nixfmt(value: &str) -> String {
     reformat_string(value)
@@ -10,11 +13,47 @@ pub fn to_string(value: Value) -> String {
     format!(r#""{value}""#)
 }
 
+/// A double quoted nix string of `value`.
+pub fn nix_string(value: String) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t");
+    format!(r#""{escaped}""#)
+}
+
+/// The `extensions` VS Code installs on `side`, those of unknown kind are on
+/// both sides.
+pub fn on_side(extensions: Vec<Value>, side: String) -> Vec<Value> {
+    extensions
+        .into_iter()
+        .filter(|item| {
+            let kinds = item
+                .get_attr("extension_kind")
+                .ok()
+                .and_then(|kinds| Vec::<ExtensionKind>::deserialize(kinds).ok())
+                .unwrap_or_default();
+            let Ok(side) = ExtensionSide::deserialize(Value::from(side.as_str())) else {
+                return false;
+            };
+            kinds.is_empty() || ExtensionSide::new(&kinds) == side
+        })
+        .collect()
+}
+
 pub fn is_universal(target_platform: String) -> bool {
     let target_platform: TargetPlatform = target_platform.as_str().into();
     matches!(target_platform, TargetPlatform::Universal)
 }
 
+pub fn is_web(target_platform: String) -> bool {
+    let target_platform: TargetPlatform = target_platform.as_str().into();
+    matches!(target_platform, TargetPlatform::Web)
+}
+
 pub fn is_linux_x86(target_platform: String) -> bool {
     let target_platform: TargetPlatform = target_platform.as_str().into();
     matches!(target_platform, TargetPlatform::LinuxX64)
@@ -1,52 +1,129 @@
 pub mod code;
+pub mod command;
 pub mod config;
 pub mod error;
 pub mod jinja;
 pub mod utils;
 
-use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
+use anyhow::anyhow;
 use code::CodeNix;
-use itertools::Itertools;
-use jinja::NixContext;
+use code_api::code::{FixtureBackend, HttpClient, ReqwestBackend};
 use tokio::fs;
 use tracing::*;
 
 use clap::Parser;
+use command::Command;
 use config::Config;
 
 use crate::jinja::{Generator, GeneratorContext};
+use crate::utils::{
+    CacheMode, CacheOptions, FixtureHasher, GALLERY_FIXTURE, Hasher, SHA256_FIXTURE, init_cacher,
+};
 
 #[derive(Debug, Parser)]
-#[command(author, version)]
+#[command(author, version, subcommand_negates_reqs = true)]
 struct Args {
-    file: String,
+    #[arg(required_unless_present = "config")]
+    file: Option<String>,
+    /// Config file layered over `FILE` and the ones before it, may be repeated
+    #[arg(short, long)]
+    config: Vec<String>,
     #[arg(short, long)]
     output: Option<String>,
+    /// Write every profile to `<name>.nix` in this directory
+    #[arg(long, conflicts_with_all = ["output", "export"])]
+    output_dir: Option<PathBuf>,
     #[arg(long, hide = true)]
     export: bool,
+    /// Write gallery responses and hashes to fixtures in this directory
+    #[arg(long, hide = true, conflicts_with_all = ["replay_fixtures", "offline"])]
+    record_fixtures: Option<PathBuf>,
+    /// Take gallery responses and hashes from fixtures instead of the network
+    #[arg(long, hide = true)]
+    replay_fixtures: Option<PathBuf>,
+    /// Directory of the cache, defaults to $XDG_CACHE_HOME/nix4vscode
+    #[arg(long, global = true, env = "NIX4VSCODE_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+    #[arg(long, global = true, value_enum, env = "NIX4VSCODE_CACHE_MODE")]
+    cache_mode: Option<CacheMode>,
+    /// Same as `--cache-mode off`
+    #[arg(long, global = true, conflicts_with = "cache_mode")]
+    no_cache: bool,
+    /// Never touch the network, gallery responses and hashes come from the cache
+    #[arg(long, global = true, conflicts_with = "no_cache")]
+    offline: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Args {
+    /// Command line and environment win over the config file.
+    fn cache_options(&self, config: Option<&Config>) -> CacheOptions {
+        let mode = match self.no_cache {
+            true => Some(CacheMode::Off),
+            false => self.cache_mode,
+        };
+
+        CacheOptions {
+            dir: self
+                .cache_dir
+                .clone()
+                .or_else(|| config.and_then(|config| config.cache_dir.clone())),
+            mode: mode
+                .or_else(|| config.and_then(|config| config.cache_mode))
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// The gallery client and the hasher, wrapped by the fixtures if asked to.
+    fn backends(&self, config: &Config) -> anyhow::Result<(HttpClient, Arc<dyn Hasher>)> {
+        if let Some(dir) = &self.replay_fixtures {
+            let client = HttpClient::new()?
+                .with_backend(Arc::new(FixtureBackend::replay(dir.join(GALLERY_FIXTURE))?));
+            let hasher = FixtureHasher::replay(dir.join(SHA256_FIXTURE))?;
+            return Ok((client, Arc::new(hasher)));
+        }
+
+        let (mut client, mut hasher) = utils::backends(config)?;
+        if let Some(dir) = &self.record_fixtures {
+            // a cached response would never reach the fixture
+            client = HttpClient::with_network(&config.network)?.with_backend(Arc::new(
+                FixtureBackend::record(
+                    dir.join(GALLERY_FIXTURE),
+                    Arc::new(ReqwestBackend::new(&config.network)?),
+                )?,
+            ));
+            hasher = Arc::new(FixtureHasher::record(dir.join(SHA256_FIXTURE), hasher)?);
+        }
+
+        Ok((client, hasher))
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     init_logger();
-    let args = Args::parse();
-
-    let config = Config::from_file(&args.file).await?;
+    let mut args = Args::parse();
+    if let Some(command) = args.command.take() {
+        let config = command::load_config(command.config()).await?;
+        init_cacher(args.cache_options(config.as_ref()))?;
+        return command.run(args.offline).await;
+    }
+    let files: Vec<&String> = args.file.iter().chain(&args.config).collect();
+    let mut config = Config::from_files(&files).await?;
+    config.offline |= args.offline;
     debug!(?config);
+    init_cacher(args.cache_options(Some(&config)))?;
 
     let mut generator = Generator::new();
-    let mut code = CodeNix::new(config.clone());
-
-    let ctx = code.get_extensions(generator.clone()).await;
-    let mut ctx2 = HashMap::<String, NixContext>::new();
-    for item in ctx {
-        ctx2.insert(
-            format!("{}-{}-{:?}", item.publisher, item.name, item.platform),
-            item,
-        );
-    }
-    let ctx = ctx2.into_values().collect_vec();
+    let (client, hasher) = args.backends(&config)?;
+    let mut code = CodeNix::new(config.clone(), client, hasher);
+
+    let ctx = code::dedup(code.get_extensions(generator.clone()).await?);
     debug!("{ctx:#?}");
 
     if args.export {
@@ -58,10 +135,21 @@ async fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
-    let res = generator.render(&GeneratorContext {
-        extensions: ctx,
-        config: config.clone().into(),
-    })?;
+    let ctx = GeneratorContext::new(config, ctx);
+    if let Some(dir) = args.output_dir {
+        if ctx.profiles.is_empty() {
+            return Err(anyhow!("--output-dir needs [profiles] in the config"));
+        }
+        fs::create_dir_all(&dir).await?;
+        for name in ctx.profiles.keys() {
+            if let Some(profile) = ctx.profile(name) {
+                fs::write(dir.join(format!("{name}.nix")), generator.render(&profile)?).await?;
+            }
+        }
+        return Ok(());
+    }
+
+    let res = generator.render(&ctx)?;
 
     match args.output {
         Some(filepath) => tokio::fs::write(filepath, res).await.unwrap(),
@@ -72,7 +160,7 @@ async fn main() -> anyhow::Result<()> {
 }
 
 fn init_logger() {
-    use tracing_subscriber::{fmt, prelude::*, util::SubscriberInitExt, EnvFilter};
+    use tracing_subscriber::{EnvFilter, fmt, prelude::*, util::SubscriberInitExt};
 
     let log_level = std::env::var("RUST_LOG")
         .unwrap_or("INFO".into())
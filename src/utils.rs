@@ -1,27 +1,61 @@
 mod cacher;
+mod hasher;
+mod http_cache;
+mod signature;
+mod vsix;
 
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
 pub use cacher::*;
+use code_api::code::HttpClient;
+use code_api::config::NetworkConfig;
+pub use hasher::*;
+pub use http_cache::*;
+pub use signature::*;
+pub use vsix::*;
 
-use crate::error::Error;
-use tracing::*;
+use crate::config::Config;
 
-pub async fn get_sha256(url: &str) -> anyhow::Result<String> {
-    trace!("get sha256 of {url}");
-    if let Ok(val) = GLOBAL_CACHER.get(CacheType::Cache256, url) {
-        return Ok(val);
-    }
+/// A client whose gallery queries go through [`HttpCache`], valid for `ttl`.
+/// An `offline` client only answers from the cache, however old the
+/// responses are.
+pub fn http_client(
+    ttl: Duration,
+    offline: bool,
+    network: &NetworkConfig,
+) -> anyhow::Result<HttpClient> {
+    let ttl = match offline {
+        true => Duration::MAX,
+        false => ttl,
+    };
+    let cache = HttpCache::new(network.gallery(), ttl);
+    Ok(HttpClient::with_network(network)?
+        .with_cache(Arc::new(cache))
+        .with_offline(offline))
+}
 
-    let sha256 = tokio::process::Command::new("nix-prefetch-url")
-        .arg(url)
-        .output()
-        .await?
-        .stdout;
+/// The gallery client and the hasher of `config`.
+pub fn backends(config: &Config) -> anyhow::Result<(HttpClient, Arc<dyn Hasher>)> {
+    let client = http_client(
+        Duration::from_secs(config.http_cache_ttl),
+        config.offline,
+        &config.network,
+    )?;
+    Ok((client, Arc::new(hasher(config)?)))
+}
 
-    let sha256 = String::from_utf8(sha256).unwrap().trim().to_owned();
-    if sha256.is_empty() {
-        return Err(Error::Sha256Error(url.into()).into());
+/// The hasher of `config`, with its `[signature]` roots.
+pub fn hasher(config: &Config) -> anyhow::Result<NixPrefetchUrl> {
+    let hasher = NixPrefetchUrl::new(config.offline, config.network.clone())?;
+    if !config.signature.verify {
+        return Ok(hasher);
     }
-
-    let _ = GLOBAL_CACHER.insert(CacheType::Cache256, url, &sha256);
-    Ok(sha256)
+    if config.signature.roots.is_empty() {
+        return Err(anyhow!(
+            "signature.roots is empty, no signature can be trusted"
+        ));
+    }
+    Ok(hasher.with_signature_roots(load_certificates(&config.signature.roots)?))
 }
@@ -1,32 +1,133 @@
-use redb::TableDefinition;
-use std::{path::PathBuf, sync::LazyLock};
+use redb::{
+    DatabaseError, ReadableTable, ReadableTableMetadata, StorageBackend, StorageError,
+    TableDefinition, TableError, WriteTransaction, backends::InMemoryBackend,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    sync::{LazyLock, OnceLock},
+    time::{Duration, Instant},
+};
 use tracing::*;
 
 use crate::error::Error;
 
-pub static GLOBAL_CACHER: LazyLock<Cacher> = LazyLock::new(|| {
-    let path = format!(
-        "{}/{}/{}",
-        std::env::var("HOME").unwrap(),
-        ".cache",
-        "nix4vscode"
-    );
-    let path = PathBuf::from(path);
-    if !path.exists() {
-        std::fs::create_dir_all(path.clone()).unwrap();
+static CACHE_OPTIONS: OnceLock<CacheOptions> = OnceLock::new();
+
+pub static GLOBAL_CACHER: LazyLock<Cacher> =
+    LazyLock::new(|| Cacher::open(&CACHE_OPTIONS.get().cloned().unwrap_or_default()));
+
+/// Configures [`GLOBAL_CACHER`], this has no effect once the cache is in use.
+pub fn init_cacher(mut options: CacheOptions) -> anyhow::Result<()> {
+    if options.mode != CacheMode::Off && options.dir.is_none() {
+        options.dir = Some(default_cache_dir()?);
     }
-    let path = path.join("cache.redb");
+    if CACHE_OPTIONS.set(options).is_err() {
+        warn!("cache is already initialized");
+    }
+    Ok(())
+}
+
+/// `$XDG_CACHE_HOME/nix4vscode`, or `~/.cache/nix4vscode` if it isn't set.
+/// Relative paths are ignored, as the XDG spec requires.
+pub fn default_cache_dir() -> anyhow::Result<PathBuf> {
+    cache_dir_of(
+        std::env::var_os("XDG_CACHE_HOME"),
+        std::env::var_os("HOME"),
+    )
+    .ok_or_else(|| {
+        anyhow::anyhow!(
+            "neither XDG_CACHE_HOME nor HOME is an absolute path, set --cache-dir or --cache-mode off"
+        )
+    })
+}
+
+fn cache_dir_of(xdg_cache_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let absolute = |dir: &PathBuf| dir.is_absolute();
+    xdg_cache_home
+        .map(PathBuf::from)
+        .filter(absolute)
+        .or_else(|| {
+            home.map(|home| PathBuf::from(home).join(".cache"))
+                .filter(absolute)
+        })
+        .map(|dir| dir.join("nix4vscode"))
+}
 
-    Cacher::new(path)
-});
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheMode {
+    #[default]
+    ReadWrite,
+    /// Use existing entries but never write new ones
+    ReadOnly,
+    /// Keep entries in memory for the current run only
+    Off,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheOptions {
+    /// Defaults to [`default_cache_dir`]
+    pub dir: Option<PathBuf>,
+    pub mode: CacheMode,
+    /// How long to wait for another process which holds the cache
+    pub lock_timeout: Duration,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            mode: Default::default(),
+            lock_timeout: Duration::from_secs(5),
+        }
+    }
+}
 
+static TABLE_META: TableDefinition<&str, u64> = TableDefinition::new("META");
 static TABLE_SHA256: TableDefinition<&str, &str> = TableDefinition::new("SHA256");
 static TABLE_HTTP_CLIENT: TableDefinition<&str, &str> = TableDefinition::new("HTTP_CLIENT");
+static TABLE_VSIX: TableDefinition<&str, &str> = TableDefinition::new("VSIX");
 
-#[derive(Debug)]
+const SCHEMA_VERSION: &str = "schema_version";
+
+/// `MIGRATIONS[n]` upgrades a cache of schema version `n` to `n + 1`, a cache
+/// without version is version 0.
+const MIGRATIONS: &[fn(&WriteTransaction) -> anyhow::Result<()>] = &[
+    // 1: create the tables of that time up front
+    |wt| {
+        wt.open_table(TABLE_SHA256)?;
+        wt.open_table(TABLE_HTTP_CLIENT)?;
+        Ok(())
+    },
+    // 2: what was found in a VSIX
+    |wt| {
+        wt.open_table(TABLE_VSIX)?;
+        Ok(())
+    },
+    // 3: gallery responses are keyed by their gallery, older ones could be of any
+    |wt| {
+        wt.open_table(TABLE_HTTP_CLIENT)?
+            .retain(|key, _| key.contains('\n'))?;
+        Ok(())
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 pub enum CacheType {
+    #[value(name = "sha256")]
     Cache256,
+    #[value(name = "http")]
     HttpClient,
+    /// What was found in the VSIX behind an url, as json.
+    #[value(name = "vsix")]
+    Vsix,
+}
+
+impl CacheType {
+    pub const ALL: [CacheType; 3] = [CacheType::Cache256, CacheType::HttpClient, CacheType::Vsix];
 }
 
 impl From<CacheType> for TableDefinition<'static, &str, &str> {
@@ -34,28 +135,180 @@ impl From<CacheType> for TableDefinition<'static, &str, &str> {
         match value {
             CacheType::Cache256 => TABLE_SHA256,
             CacheType::HttpClient => TABLE_HTTP_CLIENT,
+            CacheType::Vsix => TABLE_VSIX,
         }
     }
 }
 
-pub struct Cacher(redb::Database);
+pub struct Cacher {
+    db: redb::Database,
+    /// None if the cache only lives in memory
+    path: Option<PathBuf>,
+    read_only: bool,
+}
 
 impl Cacher {
-    fn new(path: impl AsRef<std::path::Path>) -> Self {
-        match redb::Database::builder().create(&path) {
-            Ok(db) => Self(db),
-            Err(_) => {
-                info!("open db failed. remove the older cache file.");
-                let _ = std::fs::remove_file(&path);
-                Self(redb::Database::builder().create(path).unwrap())
+    /// Never fails, a cache which cannot be opened is replaced by an
+    /// in-memory one.
+    pub fn open(options: &CacheOptions) -> Self {
+        if options.mode == CacheMode::Off {
+            return Self::in_memory();
+        }
+
+        let dir = match options.dir.clone().map_or_else(default_cache_dir, Ok) {
+            Ok(dir) => dir,
+            Err(err) => {
+                warn!("{err}, cache is disabled.");
+                return Self::in_memory();
+            }
+        };
+        let path = dir.join("cache.redb");
+
+        let res = match options.mode {
+            CacheMode::ReadOnly if !path.exists() => Ok(Self::in_memory()),
+            CacheMode::ReadOnly => Self::snapshot(&path),
+            _ => std::fs::create_dir_all(&dir)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| Self::new(&path, options.lock_timeout)),
+        };
+
+        match res {
+            Ok(cacher) => cacher,
+            Err(err) => {
+                warn!("cannot open cache {}: {err}", path.display());
+                match Self::snapshot(&path) {
+                    Ok(cacher) => {
+                        warn!("use a read-only copy of {}", path.display());
+                        cacher
+                    }
+                    Err(_) => Self::in_memory(),
+                }
             }
         }
     }
+
+    fn new(path: &Path, lock_timeout: Duration) -> anyhow::Result<Self> {
+        let start = Instant::now();
+        let db = loop {
+            match redb::Database::builder().create(path) {
+                Ok(db) => break db,
+                Err(DatabaseError::DatabaseAlreadyOpen) if start.elapsed() < lock_timeout => {
+                    debug!("cache is used by another process, waiting");
+                    std::thread::sleep(Duration::from_millis(200));
+                }
+                Err(DatabaseError::DatabaseAlreadyOpen) => {
+                    return Err(Error::CacheLocked(path.display().to_string()).into());
+                }
+                // e.g. permission denied, the file itself is fine
+                Err(DatabaseError::Storage(StorageError::Io(err)))
+                    if !matches!(
+                        err.kind(),
+                        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                    ) =>
+                {
+                    return Err(err.into());
+                }
+                Err(err) => {
+                    let backup = path.with_extension(format!("redb.{}.bak", super::now()));
+                    warn!(
+                        "cache {} is broken ({err}), move it to {}",
+                        path.display(),
+                        backup.display()
+                    );
+                    std::fs::rename(path, &backup)?;
+                    break redb::Database::builder().create(path)?;
+                }
+            }
+        };
+
+        let mut cacher = Self {
+            db,
+            path: Some(path.to_path_buf()),
+            read_only: false,
+        };
+        cacher.migrate()?;
+
+        Ok(cacher)
+    }
+
+    /// Loads `path` into memory, the file itself is never written.
+    fn snapshot(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(path)?;
+        let backend = InMemoryBackend::new();
+        backend.set_len(data.len() as u64)?;
+        StorageBackend::write(&backend, 0, &data)?;
+
+        let mut cacher = Self {
+            db: redb::Database::builder().create_with_backend(backend)?,
+            path: Some(path.to_path_buf()),
+            read_only: false,
+        };
+        // upgrades the copy only
+        cacher.migrate()?;
+        cacher.read_only = true;
+
+        Ok(cacher)
+    }
+
+    pub fn schema_version(&self) -> anyhow::Result<u64> {
+        let r_txn = self.db.begin_read()?;
+        let table = match r_txn.open_table(TABLE_META) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(table
+            .get(SCHEMA_VERSION)?
+            .map(|item| item.value())
+            .unwrap_or_default())
+    }
+
+    fn migrate(&mut self) -> anyhow::Result<()> {
+        let version = self.schema_version()? as usize;
+        if version > MIGRATIONS.len() {
+            warn!("cache is created by a newer nix4vscode, it is read-only now.");
+            self.read_only = true;
+            return Ok(());
+        }
+        if version == MIGRATIONS.len() {
+            return Ok(());
+        }
+
+        let wt = self.db.begin_write()?;
+        for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            debug!("migrate cache to version {}", idx + 1);
+            migration(&wt)?;
+        }
+        wt.open_table(TABLE_META)?
+            .insert(SCHEMA_VERSION, MIGRATIONS.len() as u64)?;
+        wt.commit()?;
+
+        Ok(())
+    }
+
+    fn in_memory() -> Self {
+        Self {
+            db: redb::Database::builder()
+                .create_with_backend(InMemoryBackend::new())
+                .expect("in-memory database"),
+            path: None,
+            read_only: false,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
 }
 
 impl Cacher {
     pub fn get(&self, cache_type: CacheType, key: &str) -> anyhow::Result<String> {
-        let r_txn = self.0.begin_read()?;
+        let r_txn = self.db.begin_read()?;
         let table = r_txn.open_table(cache_type.into())?;
         let value = table
             .get(key)?
@@ -70,18 +323,239 @@ impl Cacher {
         Ok(value)
     }
 
-    pub fn insert(&self, _cache_type: CacheType, key: &str, value: &str) -> anyhow::Result<()> {
+    pub fn insert(&self, cache_type: CacheType, key: &str, value: &str) -> anyhow::Result<()> {
         if value.is_empty() {
             // Clean cache
             return Ok(());
         }
-        let wt = self.0.begin_write()?;
+        if self.read_only {
+            trace!("cache is read-only, skip {key}");
+            return Ok(());
+        }
+        let wt = self.db.begin_write()?;
         {
-            let mut table = wt.open_table(TABLE_SHA256)?;
+            let mut table = wt.open_table(cache_type.into())?;
             table.insert(key, value)?;
         }
         wt.commit()?;
 
         Ok(())
     }
+
+    /// Inserts every entry in a single transaction, unlike [`Cacher::insert`]
+    /// a read-only cache is an error.
+    pub fn insert_all<'a>(
+        &self,
+        entries: impl IntoIterator<Item = (CacheType, &'a str, &'a str)>,
+    ) -> anyhow::Result<()> {
+        if self.read_only {
+            return Err(Error::CacheReadOnly.into());
+        }
+        let wt = self.db.begin_write()?;
+        for (cache_type, key, value) in entries {
+            if value.is_empty() {
+                continue;
+            }
+            wt.open_table(cache_type.into())?.insert(key, value)?;
+        }
+        wt.commit()?;
+
+        Ok(())
+    }
+
+    pub fn entries(&self, cache_type: CacheType) -> anyhow::Result<Vec<(String, String)>> {
+        let r_txn = self.db.begin_read()?;
+        let table = match r_txn.open_table(cache_type.into()) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut entries = vec![];
+        for item in table.iter()? {
+            let (key, value) = item?;
+            entries.push((key.value().to_string(), value.value().to_string()));
+        }
+
+        Ok(entries)
+    }
+
+    pub fn len(&self, cache_type: CacheType) -> anyhow::Result<u64> {
+        let r_txn = self.db.begin_read()?;
+        match r_txn.open_table(cache_type.into()) {
+            Ok(table) => Ok(table.len()?),
+            Err(TableError::TableDoesNotExist(_)) => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn clear(&self, cache_type: CacheType) -> anyhow::Result<()> {
+        if self.read_only {
+            return Err(Error::CacheReadOnly.into());
+        }
+        let wt = self.db.begin_write()?;
+        wt.delete_table(TableDefinition::<&str, &str>::from(cache_type))?;
+        wt.commit()?;
+
+        Ok(())
+    }
+
+    /// Removes every entry whose key matches `predicate`, returns how many were removed.
+    pub fn remove_if(
+        &self,
+        cache_type: CacheType,
+        mut predicate: impl FnMut(&str) -> bool,
+    ) -> anyhow::Result<u64> {
+        if self.read_only {
+            return Err(Error::CacheReadOnly.into());
+        }
+        let wt = self.db.begin_write()?;
+        let removed = {
+            let mut table = wt.open_table(cache_type.into())?;
+            let before = table.len()?;
+            table.retain(|key, _| !predicate(key))?;
+            before - table.len()?
+        };
+        wt.commit()?;
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache_type() {
+        let path = std::env::temp_dir().join(format!("nix4vscode-{}.redb", std::process::id()));
+        let cacher = Cacher::new(&path, Duration::ZERO).unwrap();
+
+        cacher.insert(CacheType::Cache256, "key", "sha256").unwrap();
+        cacher
+            .insert(CacheType::HttpClient, "key", "response")
+            .unwrap();
+        assert_eq!(cacher.get(CacheType::Cache256, "key").unwrap(), "sha256");
+        assert_eq!(
+            cacher.get(CacheType::HttpClient, "key").unwrap(),
+            "response"
+        );
+        assert!(cacher.get(CacheType::HttpClient, "missing").is_err());
+
+        drop(cacher);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_default_cache_dir() {
+        let dir = |xdg: &str, home: &str| cache_dir_of(Some(xdg.into()), Some(home.into()));
+
+        assert_eq!(dir("/xdg", "/home"), Some(PathBuf::from("/xdg/nix4vscode")));
+        assert_eq!(
+            dir("", "/home"),
+            Some(PathBuf::from("/home/.cache/nix4vscode"))
+        );
+        assert_eq!(dir("", ""), None);
+        assert_eq!(dir("xdg", "home"), None);
+        assert_eq!(cache_dir_of(None, None), None);
+    }
+
+    #[test]
+    fn test_cache_mode() {
+        let dir = std::env::temp_dir().join(format!("nix4vscode-mode-{}", std::process::id()));
+        let options = |mode| CacheOptions {
+            dir: Some(dir.clone()),
+            mode,
+            lock_timeout: Duration::ZERO,
+        };
+
+        let cacher = Cacher::open(&options(CacheMode::Off));
+        assert!(cacher.path().is_none());
+
+        let cacher = Cacher::open(&options(CacheMode::ReadOnly));
+        assert!(cacher.path().is_none());
+        assert!(!dir.exists());
+
+        let cacher = Cacher::open(&options(CacheMode::ReadWrite));
+        assert_eq!(cacher.path(), Some(dir.join("cache.redb").as_path()));
+        cacher.insert(CacheType::Cache256, "key", "sha256").unwrap();
+        cacher
+            .insert_all([(CacheType::Vsix, "a", "1"), (CacheType::Vsix, "b", "2")])
+            .unwrap();
+        assert_eq!(cacher.len(CacheType::Vsix).unwrap(), 2);
+
+        drop(cacher);
+
+        let cacher = Cacher::open(&options(CacheMode::ReadOnly));
+        assert!(cacher.is_read_only());
+        assert_eq!(cacher.get(CacheType::Cache256, "key").unwrap(), "sha256");
+        cacher
+            .insert(CacheType::Cache256, "other", "value")
+            .unwrap();
+        assert!(cacher.get(CacheType::Cache256, "other").is_err());
+        assert!(cacher.clear(CacheType::Cache256).is_err());
+        assert!(
+            cacher
+                .insert_all([(CacheType::Cache256, "other", "value")])
+                .is_err()
+        );
+
+        drop(cacher);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_migrations() {
+        let cacher = Cacher::in_memory();
+        let wt = cacher.db.begin_write().unwrap();
+        MIGRATIONS[0](&wt).unwrap();
+        wt.commit().unwrap();
+
+        let r_txn = cacher.db.begin_read().unwrap();
+        assert!(r_txn.open_table(TABLE_HTTP_CLIENT).is_ok());
+        assert!(matches!(
+            r_txn.open_table(TABLE_VSIX),
+            Err(TableError::TableDoesNotExist(_))
+        ));
+    }
+
+    #[test]
+    fn test_locked_and_broken() {
+        let dir = std::env::temp_dir().join(format!("nix4vscode-lock-{}", std::process::id()));
+        let options = CacheOptions {
+            dir: Some(dir.clone()),
+            mode: CacheMode::ReadWrite,
+            lock_timeout: Duration::ZERO,
+        };
+
+        let cacher = Cacher::open(&options);
+        assert_eq!(cacher.schema_version().unwrap(), MIGRATIONS.len() as u64);
+        cacher.insert(CacheType::Cache256, "key", "sha256").unwrap();
+
+        // the file is locked by `cacher`, so this is a copy of it
+        let snapshot = Cacher::open(&options);
+        assert!(snapshot.is_read_only());
+        assert_eq!(snapshot.get(CacheType::Cache256, "key").unwrap(), "sha256");
+        drop(snapshot);
+        drop(cacher);
+
+        std::fs::write(dir.join("cache.redb"), "not a database").unwrap();
+        let cacher = Cacher::open(&options);
+        assert!(!cacher.is_read_only());
+        assert!(cacher.get(CacheType::Cache256, "key").is_err());
+        let backups = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|item| {
+                item.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".bak")
+            })
+            .count();
+        assert_eq!(backups, 1);
+
+        drop(cacher);
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
@@ -0,0 +1,435 @@
+use std::fmt::Debug;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use code_api::code::{Fixture, ReqwestBackend};
+use code_api::config::NetworkConfig;
+use futures::future::BoxFuture;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+use tracing::*;
+
+use serde::{Deserialize, Serialize};
+use x509_cert::Certificate;
+use x509_cert::der::Encode;
+
+use super::{
+    CacheType, ExtensionKind, GLOBAL_CACHER, NativeFile, SignatureStatus, VsixIdentity,
+    native_files, package_extension_kind, package_license, verify_signature,
+};
+use crate::error::Error;
+
+/// File names of the fixtures written by `--record-fixtures`.
+pub const GALLERY_FIXTURE: &str = "gallery.json";
+pub const SHA256_FIXTURE: &str = "sha256.json";
+
+/// A VSIX to hash and what to check it against once downloaded.
+#[derive(Debug, Clone, Default)]
+pub struct PrefetchRequest {
+    pub url: String,
+    pub expected: Option<VsixIdentity>,
+    /// The `VsixSignature` asset of the VSIX.
+    pub signature_url: Option<String>,
+}
+
+/// What is known about the VSIX behind an url.
+#[derive(Debug, Clone, Default)]
+pub struct Prefetched {
+    pub sha256: String,
+    pub native: Vec<NativeFile>,
+    /// Only if a signature was asked for.
+    pub signature: Option<SignatureStatus>,
+    /// The `license` of its `package.json`.
+    pub license: Option<String>,
+    /// Empty if unknown.
+    pub extension_kind: Vec<ExtensionKind>,
+}
+
+/// The value of [`CacheType::Vsix`]. Fields added later are `None` in older
+/// entries, which are looked at again when they are used.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct VsixInfo {
+    native: Vec<NativeFile>,
+    signature: Option<SignatureStatus>,
+    /// The [`roots_fingerprint`] `signature` was checked against.
+    signature_roots: String,
+    #[serde(
+        deserialize_with = "deserialize_known",
+        skip_serializing_if = "Option::is_none"
+    )]
+    license: Option<Option<String>>,
+    extension_kind: Option<Vec<ExtensionKind>>,
+}
+
+impl VsixInfo {
+    fn is_complete(&self, request: &PrefetchRequest) -> bool {
+        self.license.is_some()
+            && self.extension_kind.is_some()
+            && (request.signature_url.is_none() || self.signature.is_some())
+    }
+
+    fn into_prefetched(self, sha256: String) -> Prefetched {
+        Prefetched {
+            sha256,
+            native: self.native,
+            signature: self.signature,
+            license: self.license.flatten(),
+            extension_kind: self.extension_kind.unwrap_or_default(),
+        }
+    }
+}
+
+/// A field which is present is known, even if it is `null`.
+fn deserialize_known<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Computes the nix sha256 of the file behind an url. A freshly downloaded
+/// VSIX is checked against the request first.
+pub trait Hasher: Debug + Send + Sync {
+    fn prefetch<'a>(
+        &'a self,
+        request: &'a PrefetchRequest,
+    ) -> BoxFuture<'a, anyhow::Result<Prefetched>>;
+}
+
+/// `nix-prefetch-url`, with results kept in [`CacheType::Cache256`] and
+/// [`CacheType::Vsix`]. When `offline`, a hash which is not cached is an
+/// error. Files of hosts with credentials, or which are kept, are downloaded
+/// and hashed by ourselves. Cached hashes are not verified again.
+#[derive(Debug)]
+pub struct NixPrefetchUrl {
+    pub offline: bool,
+    pub network: NetworkConfig,
+    /// The system roots plus `network.ca_certificates`, nix only takes a single
+    /// file. Removed when dropped.
+    ca_bundle: Option<NamedTempFile>,
+    backend: ReqwestBackend,
+    signature_roots: Vec<Certificate>,
+    roots_fingerprint: String,
+    /// Where downloaded VSIX are kept, see [`NixPrefetchUrl::keep_in`].
+    keep_dir: Option<PathBuf>,
+}
+
+impl NixPrefetchUrl {
+    pub fn new(offline: bool, network: NetworkConfig) -> anyhow::Result<Self> {
+        let ca_bundle = match network.ca_certificates.is_empty() {
+            true => None,
+            false => Some(write_ca_bundle(&network.ca_certificates)?),
+        };
+        Ok(Self {
+            offline,
+            backend: ReqwestBackend::new(&network)?,
+            network,
+            ca_bundle,
+            signature_roots: vec![],
+            roots_fingerprint: roots_fingerprint(&[]),
+            keep_dir: None,
+        })
+    }
+
+    /// Keeps every VSIX it downloads as [`vsix_file_name`] in `dir`, they
+    /// are then downloaded and hashed by ourselves instead of by nix.
+    pub fn keep_in(mut self, dir: PathBuf) -> Self {
+        self.keep_dir = Some(dir);
+        self
+    }
+
+    /// The certificates signatures have to chain up to.
+    pub fn with_signature_roots(mut self, roots: Vec<Certificate>) -> Self {
+        self.roots_fingerprint = roots_fingerprint(&roots);
+        self.signature_roots = roots;
+        self
+    }
+
+    fn command(&self, url: &str) -> tokio::process::Command {
+        let network = &self.network;
+        let mut command = tokio::process::Command::new("nix-prefetch-url");
+        if let Some(proxy) = &network.proxy {
+            command.env("http_proxy", proxy).env("https_proxy", proxy);
+        }
+        if let Some(no_proxy) = &network.no_proxy {
+            command.env("no_proxy", no_proxy);
+        }
+        if let Some(ca_bundle) = &self.ca_bundle {
+            command.env("NIX_SSL_CERT_FILE", ca_bundle.path());
+        }
+        if let Some(user_agent) = &network.user_agent {
+            command.args(["--option", "user-agent-suffix", user_agent]);
+        }
+        if let Some(secs) = network.connect_timeout {
+            command.args(["--option", "connect-timeout", &secs.to_string()]);
+        }
+        if let Some(secs) = network.read_timeout {
+            command.args(["--option", "stalled-download-timeout", &secs.to_string()]);
+        }
+        command.arg(url);
+        command
+    }
+}
+
+/// The file a kept VSIX is written to.
+pub fn vsix_file_name(sha256: &str) -> String {
+    format!("{sha256}.vsix")
+}
+
+/// The sha256 of `content` in the base32 encoding of nix, as printed by `nix-prefetch-url`.
+pub fn nix_sha256(content: &[u8]) -> String {
+    const CHARS: &[u8] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+    let hash = Sha256::digest(content);
+    let len = (hash.len() * 8 - 1) / 5 + 1;
+    (0..len)
+        .rev()
+        .map(|n| {
+            let (i, j) = (n * 5 / 8, n * 5 % 8);
+            let low = hash[i] as u16 >> j;
+            let high = hash.get(i + 1).map_or(0, |v| (*v as u16) << (8 - j));
+            CHARS[((low | high) & 0x1f) as usize] as char
+        })
+        .collect()
+}
+
+/// Appends `extra` to the roots nix would use otherwise, in a file of the temp
+/// dir which only we can read.
+fn write_ca_bundle(extra: &[PathBuf]) -> anyhow::Result<NamedTempFile> {
+    let system = ["NIX_SSL_CERT_FILE", "SSL_CERT_FILE"]
+        .into_iter()
+        .filter_map(|key| std::env::var_os(key).map(PathBuf::from))
+        .chain(
+            [
+                "/etc/ssl/certs/ca-certificates.crt",
+                "/etc/ssl/certs/ca-bundle.crt",
+            ]
+            .map(PathBuf::from),
+        )
+        .find(|path| path.is_file());
+
+    let mut bundle = vec![];
+    for path in system.iter().chain(extra) {
+        let pem = std::fs::read(path)
+            .map_err(|err| anyhow::anyhow!("read {} failed: {err}", path.display()))?;
+        bundle.extend(pem);
+        bundle.push(b'\n');
+    }
+
+    // created with mode 0600 under a random name
+    let mut file = tempfile::Builder::new()
+        .prefix("nix4vscode-ca-")
+        .suffix(".pem")
+        .tempfile()?;
+    file.write_all(&bundle)?;
+    file.flush()?;
+    Ok(file)
+}
+
+impl Hasher for NixPrefetchUrl {
+    fn prefetch<'a>(
+        &'a self,
+        request: &'a PrefetchRequest,
+    ) -> BoxFuture<'a, anyhow::Result<Prefetched>> {
+        Box::pin(async move {
+            let url = request.url.as_str();
+            trace!("get sha256 of {url}");
+            if let Ok(sha256) = GLOBAL_CACHER.get(CacheType::Cache256, url) {
+                let mut info: VsixInfo = GLOBAL_CACHER
+                    .get(CacheType::Vsix, url)
+                    .ok()
+                    .and_then(|value| serde_json::from_str(&value).ok())
+                    .unwrap_or_default();
+                // a verdict against other roots says nothing about these
+                if info.signature_roots != self.roots_fingerprint {
+                    info.signature = None;
+                }
+                if info.is_complete(request) || self.offline {
+                    return Ok(info.into_prefetched(sha256));
+                }
+                debug!("{url} is not fully inspected, download it again");
+            }
+            if self.offline {
+                return Err(Error::CacheMissing(url.into()).into());
+            }
+
+            let by_ourselves = self.keep_dir.is_some() || self.backend.has_credential(url);
+            let (sha256, content) = match by_ourselves {
+                true => {
+                    let content = self.backend.download(url).await?;
+                    (nix_sha256(&content), content)
+                }
+                false => {
+                    let stdout = String::from_utf8(
+                        self.command(url).arg("--print-path").output().await?.stdout,
+                    )?;
+                    let mut lines = stdout.lines().map(str::trim);
+                    let sha256 = lines.next().unwrap_or_default().to_owned();
+                    let content = match lines.next() {
+                        Some(path) => tokio::fs::read(path).await?,
+                        None => vec![],
+                    };
+                    (sha256, content)
+                }
+            };
+            if sha256.is_empty() {
+                return Err(Error::Sha256Error(url.into()).into());
+            }
+            if let Some(expected) = &request.expected {
+                expected.verify(&content)?;
+            }
+            if let Some(dir) = &self.keep_dir {
+                let path = dir.join(vsix_file_name(&sha256));
+                let part = path.with_extension("vsix.part");
+                tokio::fs::write(&part, &content).await?;
+                tokio::fs::rename(&part, &path).await?;
+            }
+            let native = native_files(&content).unwrap_or_else(|err| {
+                warn!("cannot look for native binaries in {url}: {err}");
+                vec![]
+            });
+            let signature = match &request.signature_url {
+                Some(signature_url) => {
+                    let sigzip = self.backend.download(signature_url).await?;
+                    Some(verify_signature(&content, &sigzip, &self.signature_roots))
+                }
+                None => None,
+            };
+
+            let license = package_license(&content).unwrap_or_else(|err| {
+                warn!("cannot read the license of {url}: {err}");
+                None
+            });
+
+            let extension_kind = package_extension_kind(&content).unwrap_or_else(|err| {
+                warn!("cannot read the extension kind of {url}: {err}");
+                vec![]
+            });
+
+            let info = VsixInfo {
+                native,
+                signature,
+                signature_roots: self.roots_fingerprint.clone(),
+                license: Some(license),
+                extension_kind: Some(extension_kind),
+            };
+            let _ = GLOBAL_CACHER.insert(CacheType::Cache256, url, &sha256);
+            let _ = GLOBAL_CACHER.insert(CacheType::Vsix, url, &serde_json::to_string(&info)?);
+            Ok(info.into_prefetched(sha256))
+        })
+    }
+}
+
+/// Identifies a set of signature roots, independent of their order.
+fn roots_fingerprint(roots: &[Certificate]) -> String {
+    let mut ders: Vec<Vec<u8>> = roots.iter().filter_map(|cert| cert.to_der().ok()).collect();
+    ders.sort();
+    let mut hasher = Sha256::new();
+    for der in ders {
+        hasher.update(der);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Replays hashes of a [`Fixture`], or records them from another hasher.
+/// Replayed hashes have no file to verify or to look into.
+#[derive(Debug)]
+pub struct FixtureHasher {
+    fixture: Fixture,
+    inner: Option<Arc<dyn Hasher>>,
+}
+
+impl FixtureHasher {
+    pub fn replay(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(Self {
+            fixture: Fixture::open(path)?,
+            inner: None,
+        })
+    }
+
+    pub fn record(path: impl AsRef<Path>, inner: Arc<dyn Hasher>) -> anyhow::Result<Self> {
+        Ok(Self {
+            fixture: Fixture::open(path)?,
+            inner: Some(inner),
+        })
+    }
+}
+
+impl Hasher for FixtureHasher {
+    fn prefetch<'a>(
+        &'a self,
+        request: &'a PrefetchRequest,
+    ) -> BoxFuture<'a, anyhow::Result<Prefetched>> {
+        Box::pin(async move {
+            let url = request.url.as_str();
+            match &self.inner {
+                Some(inner) => {
+                    let prefetched = inner.prefetch(request).await?;
+                    self.fixture.insert(url, &prefetched.sha256)?;
+                    Ok(prefetched)
+                }
+                None => match self.fixture.get(url) {
+                    Some(sha256) => Ok(Prefetched {
+                        sha256,
+                        ..Default::default()
+                    }),
+                    None => Err(Error::Sha256Error(url.into()).into()),
+                },
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nix_sha256() {
+        assert_eq!(
+            nix_sha256(b""),
+            "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"
+        );
+    }
+
+    #[test]
+    fn test_vsix_info() {
+        let request = PrefetchRequest::default();
+        let info = |value: &str| serde_json::from_str::<VsixInfo>(value).unwrap();
+
+        // written before licenses and extension kinds were read
+        assert!(!info(r#"{"native":[]}"#).is_complete(&request));
+        assert!(!info(r#"{"license":"MIT"}"#).is_complete(&request));
+
+        let unlicensed = info(r#"{"license":null,"extension_kind":[]}"#);
+        assert!(unlicensed.is_complete(&request));
+        assert_eq!(unlicensed.license, Some(None));
+
+        let written = VsixInfo {
+            license: Some(None),
+            extension_kind: Some(vec![]),
+            ..Default::default()
+        };
+        assert!(info(&serde_json::to_string(&written).unwrap()).is_complete(&request));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ca_bundle() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = PathBuf::from("tests/fixtures/signature/root.pem");
+        let bundle = write_ca_bundle(&[root.clone()]).unwrap();
+        let path = bundle.path().to_owned();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains(&std::fs::read_to_string(root).unwrap()));
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        drop(bundle);
+        assert!(!path.exists());
+    }
+}
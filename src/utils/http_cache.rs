@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use code_api::code::{
+    IRawGalleryExtension, IRawGalleryQueryResult, Query, QueryCache, RequestFlags,
+};
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use super::{CacheType, GLOBAL_CACHER};
+
+/// Gallery responses stored in [`CacheType::HttpClient`], valid for `ttl`.
+/// Entries are keyed by the gallery and the request body.
+#[derive(Debug)]
+pub struct HttpCache {
+    gallery: String,
+    ttl: Duration,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    /// seconds since UNIX_EPOCH
+    created: u64,
+    response: String,
+}
+
+impl HttpCache {
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+    pub fn new(gallery: &str, ttl: Duration) -> Self {
+        Self {
+            gallery: gallery.into(),
+            ttl,
+        }
+    }
+}
+
+/// The key of the response of `gallery` to `body`.
+fn cache_key(gallery: &str, body: &str) -> String {
+    format!("{gallery}\n{body}")
+}
+
+/// When a value of [`CacheType::HttpClient`] was stored, in seconds since UNIX_EPOCH.
+pub fn cached_at(value: &str) -> Option<u64> {
+    serde_json::from_str::<CachedResponse>(value)
+        .ok()
+        .map(|item| item.created)
+}
+
+/// Every extension of the cached responses of `gallery` which carry all
+/// versions and their files, keyed by the lowercase id. The newest response wins.
+pub fn cached_extensions(gallery: &str) -> anyhow::Result<HashMap<String, IRawGalleryExtension>> {
+    Ok(extensions_of(
+        GLOBAL_CACHER.entries(CacheType::HttpClient)?,
+        gallery,
+    ))
+}
+
+fn extensions_of(
+    entries: Vec<(String, String)>,
+    gallery: &str,
+) -> HashMap<String, IRawGalleryExtension> {
+    let required = RequestFlags::IncludeVersions | RequestFlags::IncludeFiles;
+    let mut responses = vec![];
+    for (key, value) in entries {
+        let Some(body) = key
+            .split_once('\n')
+            .filter(|(url, _)| *url == gallery)
+            .map(|(_, body)| body)
+        else {
+            continue;
+        };
+        let Ok(query) = serde_json::from_str::<Query>(body) else {
+            continue;
+        };
+        let flags = RequestFlags::from_bits_truncate(query.flags);
+        if !flags.contains(required) || flags.contains(RequestFlags::IncludeLatestVersionOnly) {
+            continue;
+        }
+        let Ok(cached) = serde_json::from_str::<CachedResponse>(&value) else {
+            continue;
+        };
+        match serde_json::from_str::<IRawGalleryQueryResult>(&cached.response) {
+            Ok(response) => responses.push((cached.created, response)),
+            Err(err) => debug!("bad cached response: {err}"),
+        }
+    }
+    responses.sort_by_key(|(created, _)| *created);
+
+    responses
+        .into_iter()
+        .flat_map(|(_, response)| response.results)
+        .flat_map(|item| item.extensions)
+        .map(|item| (item.to_string().to_lowercase(), item))
+        .collect()
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl QueryCache for HttpCache {
+    fn get(&self, body: &str) -> Option<String> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+
+        let value = GLOBAL_CACHER
+            .get(CacheType::HttpClient, &cache_key(&self.gallery, body))
+            .ok()?;
+        let cached: CachedResponse = serde_json::from_str(&value).ok()?;
+        if now().saturating_sub(cached.created) > self.ttl.as_secs() {
+            trace!("cached response is expired");
+            return None;
+        }
+
+        Some(cached.response)
+    }
+
+    fn insert(&self, body: &str, response: &str) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let cached = CachedResponse {
+            created: now(),
+            response: response.into(),
+        };
+        let res = serde_json::to_string(&cached)
+            .map_err(anyhow::Error::from)
+            .and_then(|value| {
+                GLOBAL_CACHER.insert(
+                    CacheType::HttpClient,
+                    &cache_key(&self.gallery, body),
+                    &value,
+                )
+            });
+        if let Err(err) = res {
+            debug!("cache response failed: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use code_api::code::HttpClient;
+    use code_api::config::Extension;
+
+    use super::*;
+
+    #[test]
+    fn test_cache_per_gallery() {
+        let private = "https://gallery.corp/_apis/public/gallery/extensionquery";
+        let body = serde_json::to_string(&Query::new(
+            &[Extension {
+                publisher_name: "foo".into(),
+                extension_name: "bar".into(),
+                asset_url: None,
+                system: None,
+            }],
+            1,
+        ))
+        .unwrap();
+        assert_ne!(
+            cache_key(HttpClient::GALLERY_URL, &body),
+            cache_key(private, &body)
+        );
+
+        let response = |publisher: &str| {
+            let response = format!(
+                r#"{{"results":[{{"extensions":[{{"extensionName":"bar","publisher":{{"publisherName":"{publisher}"}}}}]}}]}}"#
+            );
+            serde_json::to_string(&CachedResponse {
+                created: 0,
+                response,
+            })
+            .unwrap()
+        };
+        let entries = vec![
+            (
+                cache_key(HttpClient::GALLERY_URL, &body),
+                response("public"),
+            ),
+            (cache_key(private, &body), response("private")),
+        ];
+        let public = extensions_of(entries.clone(), HttpClient::GALLERY_URL);
+        assert_eq!(public.keys().collect::<Vec<_>>(), ["public.bar"]);
+        let corp = extensions_of(entries, private);
+        assert_eq!(corp.keys().collect::<Vec<_>>(), ["private.bar"]);
+    }
+}
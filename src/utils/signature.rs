@@ -0,0 +1,397 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use base64::prelude::*;
+use cms::cert::CertificateChoices;
+use cms::content_info::ContentInfo;
+use cms::signed_data::{SignedData, SignerIdentifier};
+use const_oid::ObjectIdentifier;
+use const_oid::db::{rfc5280, rfc5911, rfc5912};
+use rsa::RsaPublicKey;
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::signature::Verifier;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use x509_cert::Certificate;
+use x509_cert::der::asn1::OctetString;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::ext::pkix::{BasicConstraints, ExtendedKeyUsage};
+use zip::ZipArchive;
+
+const MANIFEST: &str = ".signature.manifest";
+const SIGNATURE: &str = ".signature.p7s";
+
+/// The outcome of checking a VSIX against its `VsixSignature` asset.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum SignatureStatus {
+    Valid {
+        signer: String,
+    },
+    /// The gallery has no signature for this version.
+    Unsigned,
+    Invalid {
+        reason: String,
+    },
+}
+
+impl SignatureStatus {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Valid { .. })
+    }
+}
+
+impl std::fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Valid { signer } => write!(f, "signed by {signer}"),
+            Self::Unsigned => write!(f, "unsigned"),
+            Self::Invalid { reason } => write!(f, "invalid signature, {reason}"),
+        }
+    }
+}
+
+/// `.signature.manifest`: the size and digests of the package and of every
+/// file in it, keyed by the base64 of their path.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    package: Digests,
+    #[serde(default)]
+    entries: Option<BTreeMap<String, Digests>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Digests {
+    size: u64,
+    digests: BTreeMap<String, String>,
+}
+
+impl Digests {
+    fn check(&self, what: &str, content: &[u8]) -> anyhow::Result<()> {
+        if self.size != content.len() as u64 {
+            return Err(anyhow!("size of {what} differs from the manifest"));
+        }
+        let sha256 = self
+            .digests
+            .get("sha256")
+            .ok_or_else(|| anyhow!("manifest has no sha256 of {what}"))?;
+        if BASE64_STANDARD.decode(sha256)? != Sha256::digest(content).as_slice() {
+            return Err(anyhow!("sha256 of {what} differs from the manifest"));
+        }
+        Ok(())
+    }
+}
+
+/// Reads every certificate of the PEM files in `paths`.
+pub fn load_certificates(paths: &[PathBuf]) -> anyhow::Result<Vec<Certificate>> {
+    let mut res = vec![];
+    for path in paths {
+        let pem =
+            std::fs::read(path).map_err(|err| anyhow!("read {} failed: {err}", path.display()))?;
+        res.extend(
+            Certificate::load_pem_chain(&pem)
+                .map_err(|err| anyhow!("parse {} failed: {err}", path.display()))?,
+        );
+    }
+    Ok(res)
+}
+
+/// Checks the signature archive `sigzip` of `vsix` the way VS Code does on
+/// install: the PKCS#7 signature has to cover the manifest and chain up to
+/// one of `roots`, and the manifest has to describe `vsix`. Validity periods
+/// are not checked, packages outlive their signing certificates.
+pub fn verify_signature(vsix: &[u8], sigzip: &[u8], roots: &[Certificate]) -> SignatureStatus {
+    match verify(vsix, sigzip, roots) {
+        Ok(signer) => SignatureStatus::Valid { signer },
+        Err(err) => SignatureStatus::Invalid {
+            reason: err.to_string(),
+        },
+    }
+}
+
+fn verify(vsix: &[u8], sigzip: &[u8], roots: &[Certificate]) -> anyhow::Result<String> {
+    let mut archive = ZipArchive::new(Cursor::new(sigzip))?;
+    let manifest = read_entry(&mut archive, MANIFEST)?;
+    let signature = read_entry(&mut archive, SIGNATURE)?;
+
+    let signer = verify_pkcs7(&signature, &manifest, roots)?;
+
+    let manifest: Manifest = serde_json::from_slice(&manifest)?;
+    manifest.package.check("the package", vsix)?;
+    if let Some(entries) = &manifest.entries {
+        let mut archive = ZipArchive::new(Cursor::new(vsix))?;
+        let mut files = 0;
+        for idx in 0..archive.len() {
+            let mut file = archive.by_index(idx)?;
+            if file.is_dir() {
+                continue;
+            }
+            files += 1;
+            let name = file.name().to_owned();
+            let digests = entries
+                .get(&BASE64_STANDARD.encode(&name))
+                .ok_or_else(|| anyhow!("{name} is not in the manifest"))?;
+            let mut content = vec![];
+            file.read_to_end(&mut content)?;
+            digests.check(&name, &content)?;
+        }
+        if files != entries.len() {
+            return Err(anyhow!(
+                "files of the manifest are missing from the package"
+            ));
+        }
+    }
+
+    Ok(signer)
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> anyhow::Result<Vec<u8>> {
+    let mut content = vec![];
+    archive
+        .by_name(name)
+        .map_err(|err| anyhow!("{name}: {err}"))?
+        .read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Verifies a detached or attached CMS signature of `content`, returns the
+/// subject of the signer.
+fn verify_pkcs7(signature: &[u8], content: &[u8], roots: &[Certificate]) -> anyhow::Result<String> {
+    let info = ContentInfo::from_der(signature)?;
+    if info.content_type != rfc5911::ID_SIGNED_DATA {
+        return Err(anyhow!("{SIGNATURE} is not signed data"));
+    }
+    let signed: SignedData = info.content.decode_as()?;
+    if let Some(econtent) = &signed.encap_content_info.econtent {
+        if econtent.decode_as::<OctetString>()?.as_bytes() != content {
+            return Err(anyhow!("{SIGNATURE} signs another manifest"));
+        }
+    }
+
+    let certs: Vec<&Certificate> = signed
+        .certificates
+        .iter()
+        .flat_map(|set| set.0.iter())
+        .filter_map(|item| match item {
+            CertificateChoices::Certificate(cert) => Some(cert),
+            _ => None,
+        })
+        .collect();
+    let signer_info = signed
+        .signer_infos
+        .0
+        .iter()
+        .next()
+        .ok_or_else(|| anyhow!("{SIGNATURE} has no signer"))?;
+    let SignerIdentifier::IssuerAndSerialNumber(sid) = &signer_info.sid else {
+        return Err(anyhow!(
+            "signer is not identified by issuer and serial number"
+        ));
+    };
+    let signer = certs
+        .iter()
+        .find(|cert| {
+            cert.tbs_certificate.issuer == sid.issuer
+                && cert.tbs_certificate.serial_number == sid.serial_number
+        })
+        .ok_or_else(|| anyhow!("certificate of the signer is missing"))?;
+
+    let digest_alg = signer_info.digest_alg.oid;
+    let signed_content = match &signer_info.signed_attrs {
+        Some(attrs) => {
+            let message_digest = attrs
+                .iter()
+                .find(|attr| attr.oid == rfc5911::ID_MESSAGE_DIGEST)
+                .and_then(|attr| attr.values.iter().next())
+                .ok_or_else(|| anyhow!("signed attributes have no message digest"))?
+                .decode_as::<OctetString>()?;
+            if message_digest.as_bytes() != digest(digest_alg, content)? {
+                return Err(anyhow!("message digest differs from the manifest"));
+            }
+            attrs.to_der()?
+        }
+        None => content.to_vec(),
+    };
+    verify_rsa(
+        signer,
+        digest_alg,
+        &signed_content,
+        signer_info.signature.as_bytes(),
+    )?;
+
+    check_key_usage(signer)?;
+    verify_chain(signer, &certs, roots)?;
+    Ok(signer.tbs_certificate.subject.to_string())
+}
+
+/// The extension `oid` of `cert`, if it has one.
+fn extension<'a, T: Decode<'a>>(
+    cert: &'a Certificate,
+    oid: ObjectIdentifier,
+) -> anyhow::Result<Option<T>> {
+    cert.tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .find(|ext| ext.extn_id == oid)
+        .map(|ext| T::from_der(ext.extn_value.as_bytes()))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// A certificate restricted to some purposes has to allow code signing.
+fn check_key_usage(cert: &Certificate) -> anyhow::Result<()> {
+    match extension::<ExtendedKeyUsage>(cert, rfc5280::ID_CE_EXT_KEY_USAGE)? {
+        Some(usage)
+            if !usage.0.iter().any(|oid| {
+                *oid == rfc5280::ID_KP_CODE_SIGNING || *oid == rfc5280::ANY_EXTENDED_KEY_USAGE
+            }) =>
+        {
+            Err(anyhow!(
+                "{} is not allowed to sign code",
+                cert.tbs_certificate.subject
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// An intermediate has to be a CA which may issue code signing certificates.
+fn check_issuer(cert: &Certificate) -> anyhow::Result<()> {
+    match extension::<BasicConstraints>(cert, rfc5280::ID_CE_BASIC_CONSTRAINTS)? {
+        Some(constraints) if constraints.ca => check_key_usage(cert),
+        _ => Err(anyhow!("{} is not a CA", cert.tbs_certificate.subject)),
+    }
+}
+
+/// Walks from `cert` over the issuers in `certs` until one of `roots` signed
+/// it, every issuer in between has to be a CA for code signing.
+fn verify_chain(
+    cert: &Certificate,
+    certs: &[&Certificate],
+    roots: &[Certificate],
+) -> anyhow::Result<()> {
+    let mut current = cert;
+    for _ in 0..=certs.len() {
+        if roots.contains(current) {
+            return Ok(());
+        }
+        let issuer = &current.tbs_certificate.issuer;
+        let signed_by = |issuer_cert: &Certificate| {
+            issuer_cert.tbs_certificate.subject == *issuer
+                && signature_digest(current.signature_algorithm.oid)
+                    .and_then(|digest_alg| {
+                        verify_rsa(
+                            issuer_cert,
+                            digest_alg,
+                            &current.tbs_certificate.to_der()?,
+                            current.signature.raw_bytes(),
+                        )
+                    })
+                    .is_ok()
+        };
+        if roots.iter().any(signed_by) {
+            return Ok(());
+        }
+        match certs
+            .iter()
+            .find(|item| **item != current && signed_by(item))
+        {
+            Some(next) => {
+                check_issuer(next)?;
+                current = next;
+            }
+            None => break,
+        }
+    }
+    Err(anyhow!(
+        "{} is not issued by a trusted root",
+        cert.tbs_certificate.subject
+    ))
+}
+
+/// The hash of an RSA signature algorithm.
+fn signature_digest(oid: ObjectIdentifier) -> anyhow::Result<ObjectIdentifier> {
+    match oid {
+        rfc5912::SHA_256_WITH_RSA_ENCRYPTION => Ok(rfc5912::ID_SHA_256),
+        rfc5912::SHA_384_WITH_RSA_ENCRYPTION => Ok(rfc5912::ID_SHA_384),
+        rfc5912::SHA_512_WITH_RSA_ENCRYPTION => Ok(rfc5912::ID_SHA_512),
+        _ => Err(anyhow!("unsupported signature algorithm {oid}")),
+    }
+}
+
+fn digest(oid: ObjectIdentifier, content: &[u8]) -> anyhow::Result<Vec<u8>> {
+    Ok(match oid {
+        rfc5912::ID_SHA_256 => Sha256::digest(content).to_vec(),
+        rfc5912::ID_SHA_384 => Sha384::digest(content).to_vec(),
+        rfc5912::ID_SHA_512 => Sha512::digest(content).to_vec(),
+        _ => return Err(anyhow!("unsupported digest algorithm {oid}")),
+    })
+}
+
+/// PKCS#1 v1.5 with the RSA key of `cert`, the only scheme code signing uses.
+fn verify_rsa(
+    cert: &Certificate,
+    digest_alg: ObjectIdentifier,
+    content: &[u8],
+    signature: &[u8],
+) -> anyhow::Result<()> {
+    let key =
+        RsaPublicKey::from_public_key_der(&cert.tbs_certificate.subject_public_key_info.to_der()?)?;
+    let signature = Signature::try_from(signature)?;
+    match digest_alg {
+        rfc5912::ID_SHA_256 => VerifyingKey::<Sha256>::new(key).verify(content, &signature)?,
+        rfc5912::ID_SHA_384 => VerifyingKey::<Sha384>::new(key).verify(content, &signature)?,
+        rfc5912::ID_SHA_512 => VerifyingKey::<Sha512>::new(key).verify(content, &signature)?,
+        _ => return Err(anyhow!("unsupported digest algorithm {digest_alg}")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Signed with `openssl cms -sign` by a certificate of an intermediate
+    /// below `root.pem`, `other.pem` is an unrelated root.
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/signature");
+
+    fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(format!("{FIXTURES}/{name}")).unwrap()
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let vsix = fixture("signed.vsix");
+        let sigzip = fixture("signed.sigzip");
+        let root = load_certificates(&[format!("{FIXTURES}/root.pem").into()]).unwrap();
+        let other = load_certificates(&[format!("{FIXTURES}/other.pem").into()]).unwrap();
+
+        let status = verify_signature(&vsix, &sigzip, &root);
+        assert!(status.is_valid(), "{status}");
+        assert!(status.to_string().contains("nix4vscode test signer"));
+
+        let status = verify_signature(&vsix, &sigzip, &other);
+        assert!(
+            status.to_string().contains("not issued by a trusted root"),
+            "{status}"
+        );
+
+        // signed by a certificate which is not a CA itself
+        let status = verify_signature(
+            &vsix,
+            &fixture("leaf-issued.sigzip"),
+            &load_certificates(&[format!("{FIXTURES}/leaf-issued-root.pem").into()]).unwrap(),
+        );
+        assert!(status.to_string().contains("is not a CA"), "{status}");
+
+        let mut tampered = vsix.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(!verify_signature(&tampered, &sigzip, &root).is_valid());
+
+        assert!(!verify_signature(&vsix, &vsix, &root).is_valid());
+    }
+}
@@ -0,0 +1,447 @@
+use std::io::{Cursor, Read};
+
+use goblin::elf::Elf;
+use goblin::mach::{Mach, SingleArch};
+use lazy_regex::regex;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+use zip::ZipArchive;
+
+use crate::error::Error;
+
+/// Who an extension claims to be, either in the gallery or inside its VSIX.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VsixIdentity {
+    pub publisher: String,
+    pub name: String,
+    pub version: String,
+    /// `engines.vscode`
+    pub engine: Option<String>,
+}
+
+impl std::fmt::Display for VsixIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}@{}", self.publisher, self.name, self.version)?;
+        if let Some(engine) = &self.engine {
+            write!(f, " (vscode {engine})")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PackageJson {
+    publisher: String,
+    name: String,
+    version: String,
+    #[serde(default)]
+    engines: Engines,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Engines {
+    vscode: Option<String>,
+}
+
+impl VsixIdentity {
+    /// Reads `extension/package.json`, and `extension.vsixmanifest` which has
+    /// to agree with it if present.
+    pub fn from_vsix(content: &[u8]) -> anyhow::Result<Self> {
+        let mut archive = ZipArchive::new(Cursor::new(content))?;
+
+        let package: PackageJson =
+            serde_json::from_str(&read_entry(&mut archive, "extension/package.json")?)?;
+        let identity = Self {
+            publisher: package.publisher,
+            name: package.name,
+            version: package.version,
+            engine: package.engines.vscode,
+        };
+
+        if archive.index_for_name("extension.vsixmanifest").is_some() {
+            let manifest =
+                Self::from_manifest(&read_entry(&mut archive, "extension.vsixmanifest")?)?;
+            if let Some(field) = identity.mismatch(&manifest) {
+                return Err(Error::VsixMismatch(format!(
+                    "{field} of package.json and extension.vsixmanifest differ: {identity} != {manifest}"
+                ))
+                .into());
+            }
+        }
+
+        Ok(identity)
+    }
+
+    fn from_manifest(manifest: &str) -> anyhow::Result<Self> {
+        let identity = regex!(r#"<Identity\s[^>]*>"#)
+            .find(manifest)
+            .ok_or(Error::AttributeMissing(
+                "Identity of extension.vsixmanifest".into(),
+            ))?
+            .as_str();
+        let attr = |name: &str| {
+            regex!(r#"(\w+)="([^"]*)""#)
+                .captures_iter(identity)
+                .find(|cap| &cap[1] == name)
+                .map(|cap| cap[2].to_owned())
+                .ok_or(Error::AttributeMissing(format!(
+                    "{name} of extension.vsixmanifest"
+                )))
+        };
+        let engine =
+            regex!(r#"<Property\s+Id="Microsoft\.VisualStudio\.Code\.Engine"\s+Value="([^"]*)""#)
+                .captures(manifest)
+                .map(|cap| cap[1].to_owned());
+
+        Ok(Self {
+            publisher: attr("Publisher")?,
+            name: attr("Id")?,
+            version: attr("Version")?,
+            engine,
+        })
+    }
+
+    /// The first field `other` disagrees on. Ids are case insensitive and an
+    /// engine is only compared when both sides know it.
+    pub fn mismatch(&self, other: &Self) -> Option<&'static str> {
+        if !self.publisher.eq_ignore_ascii_case(&other.publisher) {
+            return Some("publisher");
+        }
+        if !self.name.eq_ignore_ascii_case(&other.name) {
+            return Some("name");
+        }
+        if self.version != other.version {
+            return Some("version");
+        }
+        match (&self.engine, &other.engine) {
+            (Some(a), Some(b)) if a != b => Some("engine"),
+            _ => None,
+        }
+    }
+
+    /// Fails if the VSIX in `content` is not the extension `self` describes.
+    pub fn verify(&self, content: &[u8]) -> anyhow::Result<()> {
+        let actual = Self::from_vsix(content)?;
+        match self.mismatch(&actual) {
+            Some(field) => Err(Error::VsixMismatch(format!(
+                "{field} differs, expect {self} but got {actual}"
+            ))
+            .into()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn read_package(content: &[u8]) -> anyhow::Result<serde_json::Value> {
+    let mut archive = ZipArchive::new(Cursor::new(content))?;
+    Ok(serde_json::from_str(&read_entry(
+        &mut archive,
+        "extension/package.json",
+    )?)?)
+}
+
+/// The `license` of `extension/package.json`, old packages use an object
+/// with a `type`.
+pub fn package_license(content: &[u8]) -> anyhow::Result<Option<String>> {
+    let package = read_package(content)?;
+    Ok(match &package["license"] {
+        serde_json::Value::String(license) => Some(license.clone()),
+        serde_json::Value::Object(license) => license
+            .get("type")
+            .and_then(|item| item.as_str())
+            .map(str::to_owned),
+        _ => None,
+    })
+}
+
+/// Where an extension host may run an extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtensionKind {
+    Ui,
+    Workspace,
+    Web,
+}
+
+/// The machine of a remote connection an extension is installed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtensionSide {
+    /// The local machine running the editor.
+    Ui,
+    /// The SSH host or container holding the workspace.
+    Workspace,
+}
+
+impl ExtensionSide {
+    /// The first of `kinds` VS Code can run outside a browser wins, web only
+    /// extensions run in the editor.
+    pub fn new(kinds: &[ExtensionKind]) -> Self {
+        kinds
+            .iter()
+            .find_map(|kind| match kind {
+                ExtensionKind::Ui => Some(Self::Ui),
+                ExtensionKind::Workspace => Some(Self::Workspace),
+                ExtensionKind::Web => None,
+            })
+            .unwrap_or(Self::Ui)
+    }
+}
+
+/// The `extensionKind` of `extension/package.json`, or what VS Code deduces
+/// from `main`, `browser` and extension packs if it is missing.
+pub fn package_extension_kind(content: &[u8]) -> anyhow::Result<Vec<ExtensionKind>> {
+    let package = read_package(content)?;
+    let declared: Vec<ExtensionKind> = match &package["extensionKind"] {
+        serde_json::Value::Null => vec![],
+        kind @ serde_json::Value::String(_) => vec![serde_json::from_value(kind.clone())?],
+        kinds => serde_json::from_value(kinds.clone())?,
+    };
+    if !declared.is_empty() {
+        return Ok(declared);
+    }
+
+    let non_empty = |key: &str| package[key].as_array().is_some_and(|item| !item.is_empty());
+    Ok(
+        match (package["main"].is_string(), package["browser"].is_string()) {
+            (true, true) => vec![ExtensionKind::Workspace, ExtensionKind::Web],
+            (true, false) => vec![ExtensionKind::Workspace],
+            (false, true) => vec![ExtensionKind::Web],
+            _ if non_empty("extensionPack") || non_empty("extensionDependencies") => {
+                vec![ExtensionKind::Workspace, ExtensionKind::Web]
+            }
+            // declarative, e.g. themes and grammars
+            _ => vec![
+                ExtensionKind::Ui,
+                ExtensionKind::Workspace,
+                ExtensionKind::Web,
+            ],
+        },
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryFormat {
+    Elf,
+    MachO,
+}
+
+/// An executable or shared object shipped in a VSIX.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NativeFile {
+    /// Relative to the `extension` directory.
+    pub path: String,
+    pub format: BinaryFormat,
+    /// The dynamic loader of an ELF executable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<String>,
+    /// Libraries it links against, by soname or install name.
+    #[serde(default)]
+    pub libraries: Vec<String>,
+}
+
+/// Every ELF and Mach-O file below `extension/`, files which only look like
+/// one (java classes share the magic of fat Mach-O) are skipped.
+pub fn native_files(content: &[u8]) -> anyhow::Result<Vec<NativeFile>> {
+    let mut archive = ZipArchive::new(Cursor::new(content))?;
+    let mut res = vec![];
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+        let Some(path) = file.name().strip_prefix("extension/").map(str::to_owned) else {
+            continue;
+        };
+        if file.is_dir() || file.size() < 4 {
+            continue;
+        }
+
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        let format = match magic {
+            [0x7f, b'E', b'L', b'F'] => BinaryFormat::Elf,
+            [0xfe, 0xed, 0xfa, 0xce | 0xcf]
+            | [0xce | 0xcf, 0xfa, 0xed, 0xfe]
+            | [0xca, 0xfe, 0xba, 0xbe] => BinaryFormat::MachO,
+            _ => continue,
+        };
+        let mut bytes = magic.to_vec();
+        file.read_to_end(&mut bytes)?;
+
+        match parse_native(path.clone(), format, &bytes) {
+            Ok(native) => res.push(native),
+            Err(err) => debug!("skip {path}: {err}"),
+        }
+    }
+
+    Ok(res)
+}
+
+fn parse_native(path: String, format: BinaryFormat, bytes: &[u8]) -> anyhow::Result<NativeFile> {
+    let (interpreter, libraries) = match format {
+        BinaryFormat::Elf => {
+            let elf = Elf::parse(bytes)?;
+            (
+                elf.interpreter.map(str::to_owned),
+                elf.libraries.iter().map(|lib| lib.to_string()).collect(),
+            )
+        }
+        BinaryFormat::MachO => {
+            let mut libraries: Vec<String> = vec![];
+            let mut add = |libs: &[&str]| {
+                // goblin lists the binary itself as "self"
+                for lib in libs.iter().filter(|lib| **lib != "self") {
+                    if !libraries.iter().any(|item| item == lib) {
+                        libraries.push(lib.to_string());
+                    }
+                }
+            };
+            match Mach::parse(bytes)? {
+                Mach::Binary(macho) => add(&macho.libs),
+                Mach::Fat(fat) => {
+                    for arch in &fat {
+                        if let SingleArch::MachO(macho) = arch? {
+                            add(&macho.libs);
+                        }
+                    }
+                }
+            }
+            (None, libraries)
+        }
+    };
+
+    Ok(NativeFile {
+        path,
+        format,
+        interpreter,
+        libraries,
+    })
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> anyhow::Result<String> {
+    let mut content = String::new();
+    archive.by_name(name)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    fn vsix(package: &str, manifest: Option<&str>) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        writer
+            .start_file("extension/package.json", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(package.as_bytes()).unwrap();
+        if let Some(manifest) = manifest {
+            writer
+                .start_file("extension.vsixmanifest", SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(manifest.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_native_files() {
+        let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in [
+            ("extension/package.json", b"{}".as_slice()),
+            ("extension/bin/server", &exe),
+            (
+                "extension/lib/Foo.class",
+                b"\xca\xfe\xba\xbe\x00\x00\x00\x34",
+            ),
+        ] {
+            let options =
+                SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+            writer.start_file(name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        let content = writer.finish().unwrap().into_inner();
+
+        let files = native_files(&content).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "bin/server");
+        assert_eq!(files[0].format, BinaryFormat::Elf);
+        assert!(
+            files[0]
+                .libraries
+                .iter()
+                .any(|lib| lib.starts_with("libc.so"))
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let package = r#"{"publisher": "vadimcn", "name": "vscode-lldb", "version": "1.10.0", "engines": {"vscode": "^1.60.0"}}"#;
+        let manifest = r#"<?xml version="1.0" encoding="utf-8"?>
+<PackageManifest Version="2.0.0" xmlns="http://schemas.microsoft.com/developer/vsx-schema/2011">
+  <Metadata>
+    <Identity Language="en-US" Id="vscode-lldb" Version="1.10.0" Publisher="vadimcn" TargetPlatform="linux-x64"/>
+    <Properties>
+      <Property Id="Microsoft.VisualStudio.Code.Engine" Value="^1.60.0" />
+    </Properties>
+  </Metadata>
+</PackageManifest>"#;
+
+        let expected = VsixIdentity {
+            publisher: "vadimcn".into(),
+            name: "vscode-lldb".into(),
+            version: "1.10.0".into(),
+            engine: Some("^1.60.0".into()),
+        };
+        expected.verify(&vsix(package, Some(manifest))).unwrap();
+        expected.verify(&vsix(package, None)).unwrap();
+
+        let other = VsixIdentity {
+            version: "1.9.2".into(),
+            ..expected.clone()
+        };
+        assert!(other.verify(&vsix(package, Some(manifest))).is_err());
+
+        let manifest = manifest.replace(r#"Id="vscode-lldb""#, r#"Id="codelldb""#);
+        assert!(expected.verify(&vsix(package, Some(&manifest))).is_err());
+    }
+
+    #[test]
+    fn test_package_license() {
+        let license = |package: &str| package_license(&vsix(package, None)).unwrap();
+        assert_eq!(license(r#"{"license": "MIT"}"#).as_deref(), Some("MIT"));
+        assert_eq!(
+            license(r#"{"license": {"type": "Apache-2.0", "url": "https://x"}}"#).as_deref(),
+            Some("Apache-2.0")
+        );
+        assert_eq!(license("{}"), None);
+    }
+
+    #[test]
+    fn test_package_extension_kind() {
+        use ExtensionKind::*;
+
+        let kind = |package: &str| package_extension_kind(&vsix(package, None)).unwrap();
+        assert_eq!(kind(r#"{"extensionKind": "ui"}"#), [Ui]);
+        assert_eq!(
+            kind(r#"{"extensionKind": ["workspace", "ui"], "main": "x.js"}"#),
+            [Workspace, Ui]
+        );
+        assert_eq!(kind(r#"{"main": "x.js"}"#), [Workspace]);
+        assert_eq!(kind(r#"{"browser": "x.js"}"#), [Web]);
+        assert_eq!(kind(r#"{"extensionPack": ["a.b"]}"#), [Workspace, Web]);
+        assert_eq!(kind("{}"), [Ui, Workspace, Web]);
+
+        assert_eq!(ExtensionSide::new(&kind("{}")), ExtensionSide::Ui);
+        assert_eq!(
+            ExtensionSide::new(&[Web, Workspace]),
+            ExtensionSide::Workspace
+        );
+        assert_eq!(ExtensionSide::new(&[Web]), ExtensionSide::Ui);
+    }
+}